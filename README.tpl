@@ -27,6 +27,86 @@ You can record some data from the camera and then play it back
 at a later time.
 The playback API is very close to the live API. The callbacks are
 identical. See the examples for usage.
+Recordings start with a header that stores the format version, so
+files written by an incompatible version of this crate are rejected
+with an error instead of being misread.
+Recordings end with an index of frame offsets so the player can
+jump around with `seek_to_frame`, `seek_to_time` and `step_back`
+without decoding the whole file.
+Playback can be paused and resumed, played in reverse with `set_reverse`
+and looped over part of the recording with `set_loop` and `set_loop_range`.
+`on_wrap` and `on_end` call back when playback loops or finishes.
+While paused `update` sleeps for a frame's time instead of playing one.
+The index also stores each frame's stream sizes and skeleton count, so
+`nuitrack_rs::recording_info(path)` can report the frame count, duration,
+streams, resolution, bytes per stream and most skeletons in a frame
+without reading any frames. A recording that was never finished has no
+index, so it is rebuilt by decompressing every frame, which takes longer.
+Any subset of skeleton, depth, color and user frames can be recorded. During playback
+only the callbacks for streams stored in the recording are called.
+Depth is compressed losslessly with RVL by default. The codec is stored
+in the header and can be chosen with `Recorder::builder().depth_codec()`.
+Compare the codecs with
+`cargo run --release --example depth_codec_bench [recording]`.
+Color is stored as packed BGR bytes by default. Use
+`Recorder::builder().color_codec()` to pick `ColorCodec::Yuv420`, which
+halves the size, or `ColorCodec::Jpeg { quality }` for much smaller files.
+The player decodes either automatically.
+`Recorder::spawn()` writes on a background thread. Captures use bounded
+queues that block, drop the oldest frame or drop the newest frame when the
+disk falls behind. The handle reports how many frames were dropped.
+When one stream drops a frame the other streams of that update are
+skipped too and counted as dropped, so the streams in a frame always
+belong together.
+Every frame carries a CRC. If a recording is cut short, for example by a
+power cut, the player stops at the last good frame. Call
+`nui.set_on_corrupt(OnCorrupt::Skip)` to skip damaged frames instead.
+`nuitrack_rs::recover(path)` rewrites a damaged file into a clean one.
+Long sessions can be split into files with
+`Recorder::builder().rotate_after_duration()`, `rotate_after_bytes()` or
+`rotate_after_frames()`. Each file has its own header and plays on its own.
+Pass the directory to `playback`, or the files to `playback_files`, to play
+them back to back as one recording. A directory plays its latest session,
+use `playback_session` to pick another.
+`TriggeredRecorder` wraps a `Recorder` to only keep interesting moments.
+It holds the last few seconds of frames in memory and writes them, plus
+the frames that follow, when a trigger fires: a manual call, a user
+appearing in the skeleton data or your own predicate. Each event gets its
+own file.
+Files are only made once there is a frame to write, so a trigger that
+never fires leaves no file behind.
+The `nuitrack-tool` binary inspects and edits recordings:
+`info`, `trim --from --to`, `concat a b -o c`, `strip-stream color`,
+`downsample --every N`, `verify` and `transcode`. Run it without arguments for usage.
+The same operations are available as functions such as
+`nuitrack_rs::trim_recording`.
+Recordings made by older versions of this crate, JSON inside snappy,
+still play back. Convert them to the current format with
+`nuitrack_rs::transcode_legacy(old, new)` or `nuitrack-tool transcode`.
+
+__Export__
+`SkeletonTable` writes joints as CSV or JSON Lines for pandas, R and the like,
+either one row per joint (`TableLayout::Long`) or one row per skeleton
+(`TableLayout::Wide`). Feed it live `SkeletonData` or call
+`nuitrack_rs::export_skeleton_table(recording, output, format, layout)`.
+`BvhWriter` turns one skeleton's motion into BVH for Blender or MotionBuilder,
+and `nuitrack_rs::export_bvh` does the same for a recording.
+`GltfWriter` and `nuitrack_rs::export_gltf` write a binary glTF skin with
+one animation per skeleton id, ready for three.js.
+`nuitrack_rs::write_c3d` writes any frames of skeletons as C3D, one labelled
+point per joint in millimetres with the confidence as the residual.
+`nuitrack_rs::export_c3d` does the same for a recording, taking the frame
+rate from its time stamps.
+Long recordings and many skeletons are fine, past 65535 frames the count is
+in `TRIAL:ACTUAL_END_FIELD` and past 255 points the labels go on in `LABELS2`.
+Depth and color frames have `save_image(path, ImageFormat::Png)`. Depth is
+saved as 16 bit grey in millimetres and color as RGB.
+`nuitrack_rs::export_images` saves every frame of a recording, named by
+frame index or time stamp.
+`PointCloud::from_depth` projects a depth frame into 3D with the camera's
+`Intrinsics`, can be colorized from a color frame and cut down to one user
+from a user frame, and is saved as ascii or binary PLY.
+`nuitrack_rs::export_point_clouds` does this for every frame of a recording.
 
 __Rotation__
 You can set the camera's rotation by calling:
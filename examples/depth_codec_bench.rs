@@ -0,0 +1,118 @@
+//! Compares the depth codecs against the old JSON encoding.
+//!
+//! Uses the depth frames from a recording if a path is given,
+//! otherwise a synthetic 640x480 scene.
+//!
+//! `cargo run --release --example depth_codec_bench [recording]`
+extern crate nuitrack_rs;
+extern crate serde_json;
+extern crate snap;
+
+use nuitrack_rs::DepthCodec;
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const ROWS: usize = 480;
+const COLS: usize = 640;
+const FRAMES: usize = 30;
+
+fn main() {
+    let frames = match env::args().nth(1) {
+        Some(path) => recorded_frames(PathBuf::from(path)),
+        None => synthetic_frames(),
+    };
+    let pixels: usize = frames.iter().map(|f| f.len()).sum();
+    println!("{} frames, {} pixels", frames.len(), pixels);
+    println!("{:<14} {:>10} {:>8} {:>12} {:>12}", "encoding", "bytes", "ratio", "encode MB/s", "decode MB/s");
+
+    bench("json+snappy", &frames, |f| {
+        let json = serde_json::to_vec(f).expect("Failed to encode json");
+        snap::Encoder::new().compress_vec(&json).expect("Failed to compress")
+    }, |b, _| {
+        let json = snap::Decoder::new().decompress_vec(b).expect("Failed to decompress");
+        serde_json::from_slice(&json).expect("Failed to decode json")
+    });
+    for codec in &[DepthCodec::Raw, DepthCodec::Rvl] {
+        let name = format!("{:?}+snappy", codec).to_lowercase();
+        bench(&name, &frames, |f| {
+            let bytes = codec.encode(f);
+            snap::Encoder::new().compress_vec(&bytes).expect("Failed to compress")
+        }, |b, len| {
+            let bytes = snap::Decoder::new().decompress_vec(b).expect("Failed to decompress");
+            codec.decode(&bytes, len).expect("Failed to decode")
+        });
+    }
+}
+
+fn bench<E, D>(name: &str, frames: &[Vec<u16>], encode: E, decode: D)
+    where
+    E: Fn(&Vec<u16>) -> Vec<u8>,
+    D: Fn(&[u8], usize) -> Vec<u16>,
+{
+    let raw_bytes: usize = frames.iter().map(|f| f.len() * 2).sum();
+    let start = Instant::now();
+    let encoded: Vec<Vec<u8>> = frames.iter().map(|f| encode(f)).collect();
+    let encode_time = start.elapsed();
+    let start = Instant::now();
+    for (e, f) in encoded.iter().zip(frames) {
+        let decoded = decode(e, f.len());
+        assert!(decoded == *f, "{} is not lossless", name);
+    }
+    let decode_time = start.elapsed();
+    let bytes: usize = encoded.iter().map(|e| e.len()).sum();
+    println!("{:<14} {:>10} {:>8.2} {:>12.1} {:>12.1}",
+             name,
+             bytes,
+             raw_bytes as f64 / bytes as f64,
+             mb_per_sec(raw_bytes, encode_time),
+             mb_per_sec(raw_bytes, decode_time));
+}
+
+fn mb_per_sec(bytes: usize, time: Duration) -> f64 {
+    let secs = time.as_secs() as f64 + time.subsec_nanos() as f64 * 1e-9;
+    bytes as f64 / 1e6 / secs
+}
+
+fn recorded_frames(path: PathBuf) -> Vec<Vec<u16>> {
+    let mut nui = nuitrack_rs::playback(path, false).expect("Couldn't create player");
+    let frames = Arc::new(Mutex::new(Vec::new()));
+    let f = frames.clone();
+    nui.depth_data(move |data| {
+        f.lock().expect("Failed to lock frames").push(data.frame().to_vec());
+    }).expect("Failed to add callback");
+    while nui.update().is_ok() {}
+    let frames = frames.lock().expect("Failed to lock frames");
+    frames.clone()
+}
+
+/// A floor and back wall with a person shaped blob in front,
+/// sensor noise and invalid (zero) pixels around the edges.
+fn synthetic_frames() -> Vec<Vec<u16>> {
+    let mut seed = 0x2545_f491u32;
+    let mut noise = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed
+    };
+    (0..FRAMES).map(|frame| {
+        let mut depth = vec![0u16; ROWS * COLS];
+        for r in 0..ROWS {
+            for c in 0..COLS {
+                if c < 12 || r < 4 || noise() % 50 == 0 {
+                    continue;
+                }
+                let wall = 3500 + (c as i32 - COLS as i32 / 2).abs() as u16;
+                let floor = if r > ROWS / 2 { (1500 + 6000 * (ROWS - r) / ROWS) as u16 } else { wall };
+                let dx = c as i32 - (200 + frame as i32 * 4);
+                let dy = r as i32 - 240;
+                let person = if dx * dx / 4 + dy * dy / 16 < 1600 { 1800 } else { u16::max_value() };
+                let d = wall.min(floor).min(person);
+                depth[r * COLS + c] = d + (noise() % 8) as u16;
+            }
+        }
+        depth
+    }).collect()
+}
@@ -134,10 +134,10 @@ fn model(app: &App) -> Model {
             color_tx.send(color).ok();
         }).expect("Failed to add callback");
 
-        // Run at ~30fps forever.
+        // Play at the recorded speed forever.
+        nui.set_speed(nuitrack::Speed::Multiplier(1.0));
         loop {
             nui.update().expect("failed to update nui player");
-            std::thread::sleep(std::time::Duration::from_millis(32));
         }
     });
     let rx = Receivers { skeletons, depth, color };
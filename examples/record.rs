@@ -1,52 +1,37 @@
 extern crate nuitrack_rs;
-use std::sync::mpsc::sync_channel;
 
 fn main(){
     // Initialized
     let mut nui = nuitrack_rs::init().expect("Failed to initialize nui");
-    let mut recorder = nuitrack_rs::record();
+    let mut recorder = nuitrack_rs::record().expect("Failed to create recorder");
 
     let skeleton_capture = recorder.new_capture();
     // Data Stream Setup
     nui.skeleton_data(move |data| {
-        let data = data.skeletons()
-            .iter()
-            .map(|s| s.make_owned())
-            .collect();
-        skeleton_capture.capture_skeleton(data);
+        skeleton_capture.capture_skeleton(data.make_owned()).ok();
     }).expect("Failed to add callback");
     
     let depth_capture = recorder.new_capture();
     // Data Stream Setup
     nui.depth_data(move |data| {
-        depth_capture.capture_depth(data.frame().to_vec());
+        depth_capture.capture_depth(data.make_owned()).ok();
     }).expect("Failed to add callback");
 
     let color_capture = recorder.new_capture();
-    let size_capture = recorder.new_capture();
     // Data Stream Setup
     nui.color_data(move |data| {
-        size_capture.capture_size((data.rows, data.cols));
-        color_capture.capture_color(data.frame().to_vec());
+        color_capture.capture_color(data.make_owned()).ok();
     }).expect("Failed to add callback");
 
+    // Write on a background thread
+    let recorder = recorder.spawn();
+
     // Running
     let nui = nui.run().expect("Failed to run nui");
 
-    let (control_tx, control_rx) = sync_channel(0);
-
-    let recorder_join = std::thread::spawn(move|| {
-        let mut control = control_rx.try_iter();
-        while control.next().is_none() {
-            recorder.write();
-        }
-        recorder.flush();
-    });
-
     for _ in 0..5 {
         nui.update().expect("Failed to update");
     }
-    control_tx.send(()).expect("Failed to signal recorder thread");
-    recorder_join.join().expect("Failed to join recorder thread");
-
+    println!("Dropped {} frames", recorder.dropped());
+    recorder.stop().expect("Failed to stop recorder");
 }
@@ -148,7 +148,11 @@ extern "C" RustResult register_skeleton_closure(void (*cb)(void *, simple::Skele
         const auto wrapper = [=](const auto arg){ 
             auto s_skeletons = to_simple(arg);
             auto len = s_skeletons.size();
-            auto sd = simple::SkeletonData{.skeletons = s_skeletons.data(), .len = len };
+            auto sd = simple::SkeletonData{
+                .skeletons = s_skeletons.data(),
+                    .len = len,
+                    .time_stamp = arg->getTimestamp()
+            };
             cb(user_data, sd);
         };
         auto id = SKELETON_TRACKER.ptr->connectOnUpdate(wrapper);
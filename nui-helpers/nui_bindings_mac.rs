@@ -492,12 +492,13 @@ pub mod root {
         pub struct SkeletonData {
             pub skeletons: *mut root::simple::Skeleton,
             pub len: usize,
+            pub time_stamp: u64,
         }
         #[test]
         fn bindgen_test_layout_SkeletonData() {
             assert_eq!(
                 ::std::mem::size_of::<SkeletonData>(),
-                16usize,
+                24usize,
                 concat!("Size of: ", stringify!(SkeletonData))
             );
             assert_eq!(
@@ -525,6 +526,16 @@ pub mod root {
                     stringify!(len)
                 )
             );
+            assert_eq!(
+                unsafe { &(*(::std::ptr::null::<SkeletonData>())).time_stamp as *const _ as usize },
+                16usize,
+                concat!(
+                    "Offset of field: ",
+                    stringify!(SkeletonData),
+                    "::",
+                    stringify!(time_stamp)
+                )
+            );
         }
         #[repr(C)]
         #[derive(Debug, Copy, Clone)]
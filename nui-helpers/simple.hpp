@@ -9,6 +9,7 @@ namespace simple {
     struct SkeletonData {
         Skeleton * skeletons; 
         size_t len;
+        uint64_t time_stamp;
     };
     struct DepthFrame {
         int rows;
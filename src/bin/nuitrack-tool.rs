@@ -0,0 +1,192 @@
+//! Inspect and edit recordings written by `Recorder`.
+//!
+//! Run without arguments for usage.
+extern crate nuitrack_rs;
+
+use nuitrack_rs::Stream;
+use std::env;
+use std::error::Error;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+const USAGE: &'static str = "\
+Usage: nuitrack-tool <command> [options]
+
+Commands:
+    info <recording>
+    trim <recording> -o <output> [--from <seconds>] [--to <seconds>]
+    concat <recording>... -o <output>
+    strip-stream <skeleton|depth|color|user> <recording> -o <output>
+    downsample <recording> -o <output> --every <n>
+    verify <recording>
+    transcode <legacy recording> -o <output>";
+
+/// The parsed command line after the command name.
+struct Args {
+    inputs: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    from: Option<Duration>,
+    to: Option<Duration>,
+    every: Option<usize>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match args.first() {
+        Some(command) => command.clone(),
+        None => usage(),
+    };
+    let args = match parse(&args[1..]) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+    let result = match command.as_str() {
+        "info" => info(args),
+        "trim" => trim(args),
+        "concat" => concat(args),
+        "strip-stream" => strip_stream(args),
+        "downsample" => downsample(args),
+        "verify" => verify(args),
+        "transcode" => transcode(args),
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        _ => usage(),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn parse(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args{ inputs: Vec::new(), output: None, from: None, to: None, every: None };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-o" | "--output" => parsed.output = Some(PathBuf::from(value()?)),
+            "--from" => parsed.from = Some(seconds(value()?)?),
+            "--to" => parsed.to = Some(seconds(value()?)?),
+            "--every" => parsed.every = Some(value()?.parse().map_err(|_| "--every needs a whole number".to_string())?),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ => parsed.inputs.push(PathBuf::from(arg)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn seconds(value: &str) -> Result<Duration, String> {
+    match value.parse::<f64>() {
+        Ok(s) if s >= 0.0 => Ok(Duration::from_micros((s * 1e6) as u64)),
+        _ => Err(format!("{} isn't a number of seconds", value)),
+    }
+}
+
+impl Args {
+    fn input(&mut self) -> Result<PathBuf, String> {
+        match self.inputs.len() {
+            1 => Ok(self.inputs.remove(0)),
+            0 => Err("No recording given".to_string()),
+            _ => Err("Too many recordings given".to_string()),
+        }
+    }
+
+    fn output(&mut self) -> Result<PathBuf, String> {
+        self.output.take().ok_or_else(|| "No output given, use -o <output>".to_string())
+    }
+}
+
+fn info(mut args: Args) -> Result<(), Box<Error>> {
+    let info = nuitrack_rs::recording_info(args.input()?)?;
+    let header = &info.header;
+    let secs = info.duration.as_secs() as f64 + info.duration.subsec_micros() as f64 * 1e-6;
+    println!("written by:    nuitrack-rs {}", header.crate_version);
+    println!("created:       {}", header.created);
+    println!("part:          {} of session {}", header.part, header.session);
+    println!("frames:        {}", info.frame_count);
+    println!("duration:      {:.3}s", secs);
+    println!("resolution:    {}x{}", header.cols, header.rows);
+    println!("codecs:        depth {:?}, color {:?}", header.codecs.depth, header.codecs.color);
+    println!("max skeletons: {}", info.max_skeletons);
+    println!("file size:     {} bytes", info.file_bytes);
+    println!("streams:");
+    for stream in info.streams() {
+        println!("    {:<10} {} bytes", stream.name(), info.stream_bytes.get(*stream));
+    }
+    Ok(())
+}
+
+fn trim(mut args: Args) -> Result<(), Box<Error>> {
+    let from = args.from.unwrap_or(Duration::from_secs(0));
+    let written = nuitrack_rs::trim_recording(args.input()?, args.output()?, from, args.to)?;
+    println!("Wrote {} frames", written);
+    Ok(())
+}
+
+fn concat(mut args: Args) -> Result<(), Box<Error>> {
+    if args.inputs.is_empty() {
+        return Err("No recordings given".into());
+    }
+    let output = args.output()?;
+    let written = nuitrack_rs::concat_recordings(&args.inputs, output)?;
+    println!("Wrote {} frames", written);
+    Ok(())
+}
+
+fn strip_stream(mut args: Args) -> Result<(), Box<Error>> {
+    if args.inputs.is_empty() {
+        return Err("No stream given".into());
+    }
+    let name = args.inputs.remove(0);
+    let name = name.to_string_lossy();
+    let stream = Stream::from_name(&name)
+        .ok_or_else(|| format!("Unknown stream {}", name))?;
+    let written = nuitrack_rs::strip_stream(args.input()?, args.output()?, stream)?;
+    println!("Wrote {} frames", written);
+    Ok(())
+}
+
+fn downsample(mut args: Args) -> Result<(), Box<Error>> {
+    let every = match args.every {
+        Some(every) if every > 0 => every,
+        _ => return Err("Use --every <n> with n above zero".into()),
+    };
+    let written = nuitrack_rs::downsample_recording(args.input()?, args.output()?, every)?;
+    println!("Wrote {} frames", written);
+    Ok(())
+}
+
+fn verify(mut args: Args) -> Result<(), Box<Error>> {
+    let report = nuitrack_rs::verify_recording(args.input()?)?;
+    if report.legacy {
+        println!("A legacy recording, nuitrack-tool transcode converts it to the current format");
+    } else if !report.indexed {
+        println!("The index is missing or damaged, nuitrack_rs::recover can rebuild it");
+    }
+    for (frame, error) in &report.damaged {
+        println!("frame {}: {}", frame, error);
+    }
+    println!("{} frames, {} damaged", report.frame_count, report.damaged.len());
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err("The recording is damaged".into())
+    }
+}
+
+fn transcode(mut args: Args) -> Result<(), Box<Error>> {
+    let written = nuitrack_rs::transcode_legacy(args.input()?, args.output()?)?;
+    println!("Wrote {} frames", written);
+    Ok(())
+}
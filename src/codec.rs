@@ -0,0 +1,445 @@
+//! Per stream encodings used inside recorded frames.
+//!
+//! The chosen codecs are stored in the `Header` so a player
+//! knows how to decode each stream.
+
+use errors::NuiError;
+use image::{self, ImageFormat, ColorType};
+use image::jpeg::JPEGEncoder;
+use nui::tdv::nuitrack::Color3;
+
+/// How each stream is encoded in a recording.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Codecs {
+    pub depth: DepthCodec,
+    pub color: ColorCodec,
+}
+
+/// Lossless encodings for depth frames.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthCodec {
+    /// Little endian `u16` values.
+    Raw,
+    /// Run length encoding of zero pixels with variable length,
+    /// delta coded valid pixels (RVL, Wilson 2017).
+    /// Much smaller and faster than `Raw` for sensor data
+    /// because invalid pixels are zero and neighbours are close.
+    Rvl,
+}
+
+impl Default for DepthCodec {
+    fn default() -> Self {
+        DepthCodec::Rvl
+    }
+}
+
+impl DepthCodec {
+    pub fn encode(&self, depth: &[u16]) -> Vec<u8> {
+        match self {
+            DepthCodec::Raw => {
+                let mut out = Vec::with_capacity(depth.len() * 2);
+                for d in depth {
+                    out.push(*d as u8);
+                    out.push((*d >> 8) as u8);
+                }
+                out
+            },
+            DepthCodec::Rvl => encode_rvl(depth),
+        }
+    }
+
+    /// `len` is the number of pixels in the frame.
+    pub fn decode(&self, data: &[u8], len: usize) -> Result<Vec<u16>, NuiError> {
+        match self {
+            DepthCodec::Raw => {
+                if data.len() != len * 2 {
+                    return Err(NuiError::Corrupt(format!("expected {} bytes of depth but found {}", len * 2, data.len())));
+                }
+                Ok(data.chunks(2).map(|b| b[0] as u16 | (b[1] as u16) << 8).collect())
+            },
+            DepthCodec::Rvl => decode_rvl(data, len),
+        }
+    }
+}
+
+/// Encodings for color frames.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorCodec {
+    /// Packed blue, green, red bytes. Lossless.
+    Bgr,
+    /// Full resolution brightness with the color of each 2x2 block averaged.
+    /// Half the size of `Bgr` and hard to tell apart.
+    Yuv420,
+    /// JPEG with a quality from 1 (smallest) to 100 (best).
+    Jpeg { quality: u8 },
+}
+
+impl Default for ColorCodec {
+    fn default() -> Self {
+        ColorCodec::Bgr
+    }
+}
+
+impl ColorCodec {
+    /// `color` must have `rows * cols` pixels.
+    pub fn encode(&self, color: &[Color3], rows: i32, cols: i32) -> Result<Vec<u8>, NuiError> {
+        match *self {
+            ColorCodec::Bgr => {
+                let mut out = Vec::with_capacity(color.len() * 3);
+                for c in color {
+                    out.extend_from_slice(&[c.blue, c.green, c.red]);
+                }
+                Ok(out)
+            },
+            ColorCodec::Yuv420 => Ok(encode_yuv420(color, rows as usize, cols as usize)),
+            ColorCodec::Jpeg { quality } => {
+                if color.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let mut rgb = Vec::with_capacity(color.len() * 3);
+                for c in color {
+                    rgb.extend_from_slice(&[c.red, c.green, c.blue]);
+                }
+                let mut out = Vec::new();
+                JPEGEncoder::new_with_quality(&mut out, quality.max(1).min(100))
+                    .encode(&rgb, cols as u32, rows as u32, ColorType::RGB(8))?;
+                Ok(out)
+            },
+        }
+    }
+
+    pub fn decode(&self, data: &[u8], rows: i32, cols: i32) -> Result<Vec<Color3>, NuiError> {
+        let (rows, cols) = (rows as usize, cols as usize);
+        let color = match *self {
+            ColorCodec::Bgr => data.chunks(3)
+                .filter(|c| c.len() == 3)
+                .map(|c| Color3{ blue: c[0], green: c[1], red: c[2] })
+                .collect(),
+            ColorCodec::Yuv420 => decode_yuv420(data, rows, cols)?,
+            ColorCodec::Jpeg { .. } => {
+                if rows * cols == 0 {
+                    return Ok(Vec::new());
+                }
+                let image = image::load_from_memory_with_format(data, ImageFormat::JPEG)
+                    .map_err(|e| NuiError::Corrupt(e.to_string()))?
+                    .to_rgb();
+                if image.width() as usize != cols || image.height() as usize != rows {
+                    return Err(NuiError::Corrupt("color frame has the wrong size".to_string()));
+                }
+                image.pixels()
+                    .map(|p| Color3{ red: p[0], green: p[1], blue: p[2] })
+                    .collect()
+            },
+        };
+        if color.len() != rows * cols {
+            return Err(NuiError::Corrupt(format!("expected {} color pixels but found {}", rows * cols, color.len())));
+        }
+        Ok(color)
+    }
+}
+
+/// Planes of Y for every pixel then U and V for every 2x2 block.
+/// Uses the full range BT.601 coefficients from JPEG.
+fn encode_yuv420(color: &[Color3], rows: usize, cols: usize) -> Vec<u8> {
+    let (c_rows, c_cols) = ((rows + 1) / 2, (cols + 1) / 2);
+    let mut out = Vec::with_capacity(rows * cols + 2 * c_rows * c_cols);
+    out.extend(color.iter().map(|c| {
+        let (r, g, b) = (c.red as f32, c.green as f32, c.blue as f32);
+        clamp(0.299 * r + 0.587 * g + 0.114 * b)
+    }));
+    let mut u = Vec::with_capacity(c_rows * c_cols);
+    let mut v = Vec::with_capacity(c_rows * c_cols);
+    for cr in 0..c_rows {
+        for cc in 0..c_cols {
+            let (mut r, mut g, mut b, mut n) = (0.0, 0.0, 0.0, 0.0);
+            for row in cr * 2..(cr * 2 + 2).min(rows) {
+                for col in cc * 2..(cc * 2 + 2).min(cols) {
+                    let c = &color[row * cols + col];
+                    r += c.red as f32;
+                    g += c.green as f32;
+                    b += c.blue as f32;
+                    n += 1.0;
+                }
+            }
+            let (r, g, b) = (r / n, g / n, b / n);
+            u.push(clamp(128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b));
+            v.push(clamp(128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b));
+        }
+    }
+    out.extend(u);
+    out.extend(v);
+    out
+}
+
+fn decode_yuv420(data: &[u8], rows: usize, cols: usize) -> Result<Vec<Color3>, NuiError> {
+    let (c_rows, c_cols) = ((rows + 1) / 2, (cols + 1) / 2);
+    let chroma = c_rows * c_cols;
+    if data.len() != rows * cols + 2 * chroma {
+        return Err(NuiError::Corrupt("yuv color frame has the wrong size".to_string()));
+    }
+    let (y, uv) = data.split_at(rows * cols);
+    let (u, v) = uv.split_at(chroma);
+    let mut color = Vec::with_capacity(rows * cols);
+    for row in 0..rows {
+        for col in 0..cols {
+            let l = y[row * cols + col] as f32;
+            let block = (row / 2) * c_cols + col / 2;
+            let (cb, cr) = (u[block] as f32 - 128.0, v[block] as f32 - 128.0);
+            color.push(Color3{
+                red: clamp(l + 1.402 * cr),
+                green: clamp(l - 0.344136 * cb - 0.714136 * cr),
+                blue: clamp(l + 1.772 * cb),
+            });
+        }
+    }
+    Ok(color)
+}
+
+fn clamp(x: f32) -> u8 {
+    x.round().max(0.0).min(255.0) as u8
+}
+
+fn encode_rvl(depth: &[u16]) -> Vec<u8> {
+    let mut w = NibbleWriter{ out: Vec::with_capacity(depth.len() / 2), half: false };
+    let mut previous = 0i32;
+    let mut i = 0;
+    while i < depth.len() {
+        let zeros = depth[i..].iter().take_while(|&&d| d == 0).count();
+        w.write_vle(zeros as u32);
+        i += zeros;
+        let non_zeros = depth[i..].iter().take_while(|&&d| d != 0).count();
+        w.write_vle(non_zeros as u32);
+        for &d in &depth[i..i + non_zeros] {
+            let delta = d as i32 - previous;
+            // Zigzag so small negative deltas stay small
+            w.write_vle(((delta << 1) ^ (delta >> 31)) as u32);
+            previous = d as i32;
+        }
+        i += non_zeros;
+    }
+    w.out
+}
+
+fn decode_rvl(data: &[u8], len: usize) -> Result<Vec<u16>, NuiError> {
+    let corrupt = || NuiError::Corrupt("depth data ended early".to_string());
+    let mut r = NibbleReader{ data, pos: 0 };
+    let mut out = Vec::with_capacity(len);
+    let mut previous = 0i32;
+    while out.len() < len {
+        let zeros = r.read_vle().ok_or_else(corrupt)? as usize;
+        let non_zeros = r.read_vle().ok_or_else(corrupt)? as usize;
+        if zeros + non_zeros == 0 || out.len() + zeros + non_zeros > len {
+            return Err(NuiError::Corrupt("depth runs don't match the frame size".to_string()));
+        }
+        out.resize(out.len() + zeros, 0);
+        for _ in 0..non_zeros {
+            let z = r.read_vle().ok_or_else(corrupt)?;
+            let delta = (z >> 1) as i32 ^ -((z & 1) as i32);
+            previous += delta;
+            out.push(previous as u16);
+        }
+    }
+    Ok(out)
+}
+
+/// Packs values three bits at a time into nibbles,
+/// using the top bit of each nibble to mark that more follow.
+struct NibbleWriter {
+    out: Vec<u8>,
+    /// The last byte only has its low nibble filled.
+    half: bool,
+}
+
+impl NibbleWriter {
+    fn push(&mut self, nibble: u8) {
+        if self.half {
+            if let Some(last) = self.out.last_mut() {
+                *last |= nibble << 4;
+            }
+        } else {
+            self.out.push(nibble);
+        }
+        self.half = !self.half;
+    }
+
+    fn write_vle(&mut self, mut value: u32) {
+        loop {
+            let mut nibble = (value & 0x7) as u8;
+            value >>= 3;
+            if value != 0 {
+                nibble |= 0x8;
+            }
+            self.push(nibble);
+            if value == 0 {
+                return;
+            }
+        }
+    }
+}
+
+struct NibbleReader<'a> {
+    data: &'a [u8],
+    /// In nibbles.
+    pos: usize,
+}
+
+impl<'a> NibbleReader<'a> {
+    fn next(&mut self) -> Option<u8> {
+        let byte = *self.data.get(self.pos / 2)?;
+        let nibble = if self.pos % 2 == 0 { byte & 0xf } else { byte >> 4 };
+        self.pos += 1;
+        Some(nibble)
+    }
+
+    fn read_vle(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        let mut shift = 0;
+        loop {
+            let nibble = self.next()?;
+            value |= ((nibble & 0x7) as u32) << shift;
+            if nibble & 0x8 == 0 {
+                return Some(value);
+            }
+            shift += 3;
+            // More bits than a u32 holds
+            if shift > 30 {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic noise so failures can be reproduced.
+    fn noise(seed: &mut u32) -> u32 {
+        *seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        *seed >> 8
+    }
+
+    fn rvl_round_trip(depth: &[u16]) {
+        let encoded = DepthCodec::Rvl.encode(depth);
+        assert_eq!(DepthCodec::Rvl.decode(&encoded, depth.len()).unwrap(), depth);
+    }
+
+    #[test]
+    fn rvl_is_lossless() {
+        let mut seed = 7;
+        // Smooth surfaces with holes, at an odd width
+        let (rows, cols) = (31, 47);
+        let depth: Vec<u16> = (0..rows * cols)
+            .map(|i| if noise(&mut seed) % 5 == 0 { 0 } else { 800 + (i % cols) as u16 * 3 + (i / cols) as u16 })
+            .collect();
+        rvl_round_trip(&depth);
+        // Every value, jumping in both directions
+        let depth: Vec<u16> = (0..4001).map(|_| noise(&mut seed) as u16).collect();
+        rvl_round_trip(&depth);
+    }
+
+    #[test]
+    fn rvl_edge_frames() {
+        rvl_round_trip(&[]);
+        rvl_round_trip(&[0; 640 * 480]);
+        rvl_round_trip(&[u16::max_value(); 33 * 17]);
+        rvl_round_trip(&[u16::max_value(), 0, 1, u16::max_value(), 0, 0, u16::max_value()]);
+        rvl_round_trip(&[0, 0, 0, 5]);
+        rvl_round_trip(&[5, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rvl_rejects_wrong_length() {
+        let encoded = DepthCodec::Rvl.encode(&[1, 2, 3, 0, 0, 4]);
+        assert!(DepthCodec::Rvl.decode(&encoded, 5).is_err());
+        assert!(DepthCodec::Rvl.decode(&encoded[..1], 6).is_err());
+    }
+
+    #[test]
+    fn raw_is_lossless() {
+        let depth = [0, 1, 255, 256, u16::max_value()];
+        let encoded = DepthCodec::Raw.encode(&depth);
+        assert_eq!(encoded.len(), depth.len() * 2);
+        assert_eq!(DepthCodec::Raw.decode(&encoded, depth.len()).unwrap(), depth);
+    }
+
+    fn max_error(a: &[Color3], b: &[Color3]) -> u8 {
+        a.iter().zip(b)
+            .flat_map(|(a, b)| vec![
+                (a.red as i32 - b.red as i32).abs(),
+                (a.green as i32 - b.green as i32).abs(),
+                (a.blue as i32 - b.blue as i32).abs(),
+            ])
+            .max()
+            .unwrap_or(0) as u8
+    }
+
+    #[test]
+    fn yuv420_round_trip_error() {
+        let mut seed = 11;
+        for &(rows, cols) in &[(4, 6), (5, 7), (1, 1), (3, 2), (2, 9)] {
+            // Each 2x2 block has one color so only rounding is lost
+            let blocks: Vec<Color3> = (0..((rows + 1) / 2) * ((cols + 1) / 2))
+                .map(|_| {
+                    let n = noise(&mut seed);
+                    Color3{ red: n as u8, green: (n >> 8) as u8, blue: (n >> 16) as u8 }
+                })
+                .collect();
+            let color: Vec<Color3> = (0..rows * cols)
+                .map(|i| blocks[(i / cols / 2) * ((cols + 1) / 2) + (i % cols) / 2])
+                .collect();
+            let encoded = ColorCodec::Yuv420.encode(&color, rows as i32, cols as i32).unwrap();
+            let chroma = ((rows + 1) / 2) * ((cols + 1) / 2);
+            assert_eq!(encoded.len(), rows * cols + 2 * chroma);
+            let decoded = ColorCodec::Yuv420.decode(&encoded, rows as i32, cols as i32).unwrap();
+            assert_eq!(decoded.len(), color.len());
+            assert!(max_error(&color, &decoded) <= 3, "{}x{} is off by {}", rows, cols, max_error(&color, &decoded));
+        }
+    }
+
+    #[test]
+    fn yuv420_keeps_brightness() {
+        // A gradient's color is averaged within a block but its brightness isn't
+        let (rows, cols) = (6, 9);
+        let color: Vec<Color3> = (0..rows * cols)
+            .map(|i| {
+                let v = (i * 255 / (rows * cols)) as u8;
+                Color3{ red: v, green: v, blue: v }
+            })
+            .collect();
+        let encoded = ColorCodec::Yuv420.encode(&color, rows as i32, cols as i32).unwrap();
+        let decoded = ColorCodec::Yuv420.decode(&encoded, rows as i32, cols as i32).unwrap();
+        assert!(max_error(&color, &decoded) <= 2);
+    }
+
+    #[test]
+    fn yuv420_rejects_wrong_length() {
+        let color = vec![Color3{ red: 1, green: 2, blue: 3 }; 12];
+        let encoded = ColorCodec::Yuv420.encode(&color, 3, 4).unwrap();
+        assert!(ColorCodec::Yuv420.decode(&encoded[1..], 3, 4).is_err());
+        assert!(ColorCodec::Yuv420.decode(&encoded, 2, 6).is_err());
+    }
+
+    #[test]
+    fn jpeg_round_trip_error() {
+        // A smooth picture at an odd size, which JPEG keeps close
+        let (rows, cols) = (37, 53);
+        let color: Vec<Color3> = (0..rows * cols)
+            .map(|i| {
+                let (row, col) = (i / cols, i % cols);
+                Color3{ red: (col * 255 / cols) as u8, green: (row * 255 / rows) as u8, blue: 128 }
+            })
+            .collect();
+        let codec = ColorCodec::Jpeg { quality: 90 };
+        let encoded = codec.encode(&color, rows as i32, cols as i32).unwrap();
+        assert!(encoded.len() < color.len() * 3);
+        let decoded = codec.decode(&encoded, rows as i32, cols as i32).unwrap();
+        assert_eq!(decoded.len(), color.len());
+        // Block edges can be off by more but most pixels are close
+        assert!(max_error(&color, &decoded) <= 32, "off by {}", max_error(&color, &decoded));
+        let total: u64 = color.iter().zip(&decoded).map(|(a, b)| max_error(&[*a], &[*b]) as u64).sum();
+        assert!(total <= 4 * color.len() as u64, "off by {} on average", total as f64 / color.len() as f64);
+        // The size in the frame must match the picture
+        assert!(codec.decode(&encoded, cols as i32, rows as i32).is_err());
+    }
+}
@@ -1,8 +1,17 @@
 use nui::simple::{SkeletonData, Skeleton, DepthFrame, RGBFrame, UserFrame};
-use nui::tdv::nuitrack::{Joint, Color3, Vector3, Orientation, User};
+use nui::tdv::nuitrack::{Joint, Color3, Vector3, Orientation, User, BoundingBox};
 use nui_import::root;
 use std::slice;
 
+/// The frame id and time stamp given to a frame by nuitrack.
+///
+/// Time stamps are in microseconds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameStamp {
+    pub id: u64,
+    pub time_stamp: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SkeletonFeed {
     pub id: i32,
@@ -10,12 +19,63 @@ pub struct SkeletonFeed {
     pub joints: Vec<Joint>,
 }
 
+/// An owned copy of `SkeletonData` that can be recorded.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SkeletonDataFeed {
+    /// Microseconds.
+    pub time_stamp: u64,
+    pub skeletons: Vec<SkeletonFeed>,
+}
+
+/// An owned copy of a `DepthFrame` that can be recorded.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DepthFrameFeed {
+    pub rows: i32,
+    pub cols: i32,
+    pub stamp: FrameStamp,
+    pub data: Vec<u16>,
+}
+
+/// An owned copy of an `RGBFrame` that can be recorded.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RGBFrameFeed {
+    pub rows: i32,
+    pub cols: i32,
+    pub stamp: FrameStamp,
+    #[serde(with = "color3_vec")]
+    pub data: Vec<Color3>,
+}
+
+/// An owned copy of a `UserFrame` that can be recorded.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UserFrameFeed {
+    pub rows: i32,
+    pub cols: i32,
+    pub stamp: FrameStamp,
+    #[serde(with = "user_vec")]
+    pub users: Vec<User>,
+    /// The user id of each pixel. Zero is no user.
+    pub data: Vec<u16>,
+    #[serde(with = "Vector3Def")]
+    pub floor: Vector3,
+    #[serde(with = "Vector3Def")]
+    pub floor_normal: Vector3,
+}
+
 impl SkeletonData {
     pub fn skeletons(&self) -> &[Skeleton] {
         unsafe {
             slice::from_raw_parts(self.skeletons, self.len)
         }
     }
+
+    pub fn make_owned(&self) -> SkeletonDataFeed {
+        let skeletons = self.skeletons()
+            .iter()
+            .map(|s| s.make_owned())
+            .collect();
+        SkeletonDataFeed{ time_stamp: self.time_stamp, skeletons }
+    }
 }
 
 impl Skeleton {
@@ -39,6 +99,14 @@ impl DepthFrame {
             slice::from_raw_parts(self.data, (self.rows * self.cols) as usize)
         }
     }
+
+    pub fn stamp(&self) -> FrameStamp {
+        FrameStamp{ id: self.id, time_stamp: self.time_stamp }
+    }
+
+    pub fn make_owned(&self) -> DepthFrameFeed {
+        DepthFrameFeed{ rows: self.rows, cols: self.cols, stamp: self.stamp(), data: self.frame().to_vec() }
+    }
 }
 
 impl RGBFrame {
@@ -47,6 +115,14 @@ impl RGBFrame {
             slice::from_raw_parts(self.data, (self.rows * self.cols) as usize)
         }
     }
+
+    pub fn stamp(&self) -> FrameStamp {
+        FrameStamp{ id: self.id, time_stamp: self.time_stamp }
+    }
+
+    pub fn make_owned(&self) -> RGBFrameFeed {
+        RGBFrameFeed{ rows: self.rows, cols: self.cols, stamp: self.stamp(), data: self.frame().to_vec() }
+    }
 }
 
 impl UserFrame {
@@ -61,6 +137,22 @@ impl UserFrame {
             slice::from_raw_parts(self.data, (self.rows * self.cols) as usize)
         }
     }
+
+    pub fn stamp(&self) -> FrameStamp {
+        FrameStamp{ id: self.id, time_stamp: self.time_stamp }
+    }
+
+    pub fn make_owned(&self) -> UserFrameFeed {
+        UserFrameFeed{
+            rows: self.rows,
+            cols: self.cols,
+            stamp: self.stamp(),
+            users: self.users().to_vec(),
+            data: self.frame().to_vec(),
+            floor: self.floor,
+            floor_normal: self.floor_normal,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -86,6 +178,33 @@ pub struct Color3Def {
     pub red: u8,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "BoundingBox")]
+pub struct BoundingBoxDef {
+    pub top: f32,
+    pub bottom: f32,
+    pub left: f32,
+    pub right: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "User")]
+pub struct UserDef {
+    /// @brief %User identifier.
+    pub id: i32,
+    /// @brief Center of mass in projective coordinates.
+    #[serde(with = "Vector3Def")]
+    pub proj: root::tdv::nuitrack::Vector3,
+    /// @brief Center of mass in real coordinates.
+    #[serde(with = "Vector3Def")]
+    pub real: root::tdv::nuitrack::Vector3,
+    /// @brief Bounding box
+    #[serde(with = "BoundingBoxDef")]
+    pub box_: root::tdv::nuitrack::BoundingBox,
+    /// @brief Occlusion rate in range [0;1]
+    pub occlusion: f32,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(remote = "Joint")]
 pub struct JointDef {
@@ -159,11 +278,39 @@ pub mod color3_vec {
         }
 }
 
-impl From<&mut Vec<Skeleton>> for SkeletonData {
-    fn from(item: &mut Vec<Skeleton>) -> Self {
-        let len = item.len();
-        let skeletons = item.as_mut_ptr();
-        SkeletonData{ len, skeletons }
+mod user_vec {
+    use super::UserDef;
+    use nui::tdv::nuitrack::User;
+    use serde::{Serializer, Deserialize, Deserializer};
+    pub fn serialize<S>(array: &[User], serializer: S) -> Result<S::Ok, S::Error>
+        where
+        S: Serializer,
+        {
+            #[derive(Serialize)]
+            struct W<'a>(#[serde(with = "UserDef")] &'a User);
+
+            let map = array.iter().map(|& ref n| W(n));
+            serializer.collect_seq(map)
+        }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<User>, D::Error>
+        where
+        D: Deserializer<'de>,
+        {
+            #[derive(Deserialize)]
+            struct W(#[serde(with = "UserDef")] User);
+
+            let users = Vec::<W>::deserialize(deserializer)?;
+            Ok(users.into_iter().map(|n| n.0).collect())
+        }
+}
+
+impl From<(&mut Vec<Skeleton>, u64)> for SkeletonData {
+    fn from(item: (&mut Vec<Skeleton>, u64)) -> Self {
+        let len = item.0.len();
+        let skeletons = item.0.as_mut_ptr();
+        let time_stamp = item.1;
+        SkeletonData{ len, skeletons, time_stamp }
     }
 }
 
@@ -179,20 +326,36 @@ pub fn feed_to_ptr(item: &Vec<SkeletonFeed>) -> Vec<Skeleton> {
     .collect()
 }
 
-impl From<(&mut Vec<u16>, i32, i32)> for DepthFrame {
-    fn from(item: (&mut Vec<u16>, i32, i32)) -> Self {
-        let rows = item.1;
-        let cols = item.2;
-        let data = item.0.as_ptr();
-        DepthFrame{rows, cols, id: 0, data, time_stamp: 0}
+impl From<&DepthFrameFeed> for DepthFrame {
+    fn from(item: &DepthFrameFeed) -> Self {
+        let DepthFrameFeed{ rows, cols, ref data, .. } = *item;
+        let FrameStamp{ id, time_stamp } = item.stamp;
+        DepthFrame{rows, cols, id, data: data.as_ptr(), time_stamp}
     }
 }
 
-impl From<(&mut Vec<Color3>, i32, i32)> for RGBFrame {
-    fn from(item: (&mut Vec<Color3>, i32, i32)) -> Self {
-        let rows = item.1;
-        let cols = item.2;
-        let data = item.0.as_ptr();
-        RGBFrame{rows, cols, id: 0, data, time_stamp: 0}
+impl From<&RGBFrameFeed> for RGBFrame {
+    fn from(item: &RGBFrameFeed) -> Self {
+        let RGBFrameFeed{ rows, cols, ref data, .. } = *item;
+        let FrameStamp{ id, time_stamp } = item.stamp;
+        RGBFrame{rows, cols, id, data: data.as_ptr(), time_stamp}
+    }
+}
+
+impl From<&mut UserFrameFeed> for UserFrame {
+    fn from(item: &mut UserFrameFeed) -> Self {
+        let UserFrameFeed{ rows, cols, ref mut users, ref data, floor, floor_normal, .. } = *item;
+        let FrameStamp{ id, time_stamp } = item.stamp;
+        UserFrame{
+            num_users: users.len(),
+            users: users.as_mut_ptr(),
+            rows,
+            cols,
+            id,
+            data: data.as_ptr(),
+            time_stamp,
+            floor,
+            floor_normal,
+        }
     }
 }
@@ -0,0 +1,268 @@
+//! Whole file operations on recordings.
+//!
+//! Each one reads frames from the inputs and writes them
+//! with a `Recorder` using the same codecs.
+//! An edit that would write no frames fails with `NuiError::BadEdit`.
+
+use std::fs::{self, File};
+use std::io::{BufReader, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+use errors::NuiError;
+use format::{self, Header, IndexEntry, Stream};
+use legacy;
+use recorder::{Recorder, TimePoint};
+
+/// The result of checking every frame of a recording.
+#[derive(Clone, Debug)]
+pub struct VerifyReport {
+    pub frame_count: usize,
+    /// False if the index was missing or damaged and had to be rebuilt.
+    pub indexed: bool,
+    /// A recording made before the binary format. These never have an index.
+    pub legacy: bool,
+    /// The frames that failed to decode and why.
+    pub damaged: Vec<(usize, String)>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        (self.indexed || self.legacy) && self.damaged.is_empty()
+    }
+}
+
+/// Reads the frames of a recording in order.
+/// Legacy recordings are read too.
+pub(crate) struct Frames {
+    pub header: Header,
+    reader: BufReader<File>,
+    index: Vec<IndexEntry>,
+    legacy: bool,
+    /// The next frame to be read.
+    position: usize,
+}
+
+impl Frames {
+    pub fn open(path: &Path) -> Result<Self, NuiError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        if legacy::is_legacy(&mut reader)? {
+            let header = legacy::read_header(&mut reader)?;
+            let index = legacy::read_index(&mut reader)?;
+            return Ok(Frames{ header, reader, index, legacy: true, position: 0 });
+        }
+        let header = Header::read(&mut reader)?;
+        let data_start = reader.seek(SeekFrom::Current(0))?;
+        let index = format::read_index(&mut reader, data_start)?;
+        Ok(Frames{ header, reader, index, legacy: false, position: 0 })
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// The time stamp of the first frame.
+    fn start(&self) -> u64 {
+        self.index.first().map_or(0, |e| e.time_stamp)
+    }
+
+    /// The time stamp of the last frame.
+    fn end(&self) -> u64 {
+        self.index.last().map_or(0, |e| e.time_stamp)
+    }
+
+    /// The average time between frames, or `None` with fewer than two frames.
+    fn frame_time(&self) -> Option<u64> {
+        match self.index.len() {
+            0 | 1 => None,
+            n => Some((self.end() - self.start()) / (n as u64 - 1)),
+        }
+    }
+}
+
+impl Iterator for Frames {
+    type Item = Result<TimePoint, NuiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = *self.index.get(self.position)?;
+        self.position += 1;
+        if let Err(e) = self.reader.seek(SeekFrom::Start(entry.offset)) {
+            return Some(Err(e.into()));
+        }
+        let frame = if self.legacy {
+            legacy::read_frame(&mut self.reader, (entry.time_stamp / legacy::FRAME_MICROS) as usize)
+        } else {
+            format::read_frame(&mut self.reader, &self.header.codecs)
+                .and_then(|f| f.ok_or_else(|| NuiError::Corrupt("frame is missing".to_string())))
+        };
+        Some(frame)
+    }
+}
+
+/// A recorder writing the same streams and codecs as `header`.
+fn recorder_like(header: &Header, streams: &[Stream], output: &Path) -> Result<Recorder, NuiError> {
+    Recorder::builder()
+        .path(output)
+        .streams(streams)
+        .depth_codec(header.codecs.depth)
+        .color_codec(header.codecs.color)
+        .build()
+}
+
+/// Writes what is left of the output. Files are only made for frames
+/// so an edit that keeps none is an error instead of a missing file.
+fn finish(mut recorder: Recorder, written: usize) -> Result<usize, NuiError> {
+    if written == 0 {
+        return Err(NuiError::BadEdit("no frames selected".to_string()));
+    }
+    recorder.flush()?;
+    Ok(written)
+}
+
+/// Writing over an input would destroy it before it is read.
+fn check_output(inputs: &[&Path], output: &Path) -> Result<(), NuiError> {
+    let output = match fs::canonicalize(output) {
+        Ok(output) => output,
+        // Doesn't exist yet
+        Err(_) => return Ok(()),
+    };
+    for input in inputs {
+        if fs::canonicalize(input)? == output {
+            return Err(NuiError::BadEdit(format!("{} is both an input and the output", output.display())));
+        }
+    }
+    Ok(())
+}
+
+/// Copies the frames between `from` and `to` after the start of the recording.
+/// Returns the number of frames written.
+pub fn trim(input: &Path, output: &Path, from: Duration, to: Option<Duration>) -> Result<usize, NuiError> {
+    check_output(&[input], output)?;
+    let frames = Frames::open(input)?;
+    let start = frames.start();
+    let from = format::micros(from);
+    let to = to.map(format::micros);
+    let mut recorder = recorder_like(&frames.header, &frames.header.streams, output)?;
+    let mut written = 0;
+    for frame in frames {
+        let frame = frame?;
+        let time = frame.time_stamp().saturating_sub(start);
+        if time < from || to.map_or(false, |to| time > to) {
+            continue;
+        }
+        recorder.push_frame(frame)?;
+        written += 1;
+    }
+    finish(recorder, written)
+}
+
+/// Joins recordings with the same streams and resolution into one.
+/// The codecs of the first are used.
+/// Each recording's time stamps are moved so it starts one frame
+/// after the end of the one before.
+pub fn concat(inputs: &[&Path], output: &Path) -> Result<usize, NuiError> {
+    check_output(inputs, output)?;
+    let first = match inputs.first() {
+        Some(first) => Frames::open(first)?.header,
+        None => return Err(NuiError::BadEdit("nothing to concatenate".to_string())),
+    };
+    let mut recorder = recorder_like(&first, &first.streams, output)?;
+    let mut written = 0;
+    // Where the next recording starts, `None` for the first
+    let mut next_start: Option<u64> = None;
+    for input in inputs {
+        let frames = Frames::open(input)?;
+        let header = &frames.header;
+        if header.streams != first.streams || (header.rows, header.cols) != (first.rows, first.cols) {
+            return Err(NuiError::BadEdit(format!("{} has different streams or resolution", input.display())));
+        }
+        if frames.len() == 0 {
+            continue;
+        }
+        let shift = next_start.map_or(0, |next| next as i64 - frames.start() as i64);
+        let end = (frames.end() as i64 + shift).max(0) as u64;
+        next_start = Some(end + frames.frame_time().unwrap_or(legacy::FRAME_MICROS));
+        for frame in frames {
+            let mut frame = frame?;
+            frame.shift_time_stamps(shift);
+            recorder.push_frame(frame)?;
+            written += 1;
+        }
+    }
+    finish(recorder, written)
+}
+
+/// Copies a recording without one of its streams.
+pub fn strip_stream(input: &Path, output: &Path, stream: Stream) -> Result<usize, NuiError> {
+    check_output(&[input], output)?;
+    let frames = Frames::open(input)?;
+    let streams: Vec<Stream> = frames.header.streams.iter().cloned().filter(|s| *s != stream).collect();
+    if streams.is_empty() {
+        return Err(NuiError::BadEdit(format!("{} is the only stream", stream.name())));
+    }
+    let mut recorder = recorder_like(&frames.header, &streams, output)?;
+    let mut written = 0;
+    for frame in frames {
+        let mut frame = frame?;
+        match stream {
+            Stream::Skeleton => frame.skeleton = None,
+            Stream::Depth => frame.depth = None,
+            Stream::Color => frame.color = None,
+            Stream::User => frame.user = None,
+        }
+        recorder.push_frame(frame)?;
+        written += 1;
+    }
+    finish(recorder, written)
+}
+
+/// Keeps the first of every `every` frames.
+pub fn downsample(input: &Path, output: &Path, every: usize) -> Result<usize, NuiError> {
+    check_output(&[input], output)?;
+    let mut frames = Frames::open(input)?;
+    // Only the kept frames are decoded
+    frames.index = frames.index.into_iter().step_by(every.max(1)).collect();
+    let mut recorder = recorder_like(&frames.header, &frames.header.streams, output)?;
+    let mut written = 0;
+    for frame in frames {
+        recorder.push_frame(frame?)?;
+        written += 1;
+    }
+    finish(recorder, written)
+}
+
+/// Rewrites a recording made before the binary format in the current one.
+pub fn transcode_legacy(input: &Path, output: &Path) -> Result<usize, NuiError> {
+    check_output(&[input], output)?;
+    let frames = Frames::open(input)?;
+    if !frames.legacy {
+        return Err(NuiError::BadEdit("the input is not a legacy recording".to_string()));
+    }
+    let mut recorder = recorder_like(&frames.header, &frames.header.streams, output)?;
+    let mut written = 0;
+    for frame in frames {
+        recorder.push_frame(frame?)?;
+        written += 1;
+    }
+    finish(recorder, written)
+}
+
+/// Decodes every frame and checks the index.
+/// Legacy recordings have no index so only their frames are checked.
+pub fn verify(path: &Path) -> Result<VerifyReport, NuiError> {
+    let frames = Frames::open(path)?;
+    let legacy = frames.legacy;
+    let indexed = !legacy && {
+        let mut reader = BufReader::new(File::open(path)?);
+        Header::read(&mut reader)?;
+        let data_start = reader.seek(SeekFrom::Current(0))?;
+        format::has_index(&mut reader, data_start)?
+    };
+    let frame_count = frames.len();
+    let damaged = frames.enumerate()
+        .filter_map(|(i, f)| match f {
+            Ok(_) => None,
+            Err(e) => Some((i, e.to_string())),
+        })
+        .collect();
+    Ok(VerifyReport{ frame_count, indexed, legacy, damaged })
+}
@@ -1,15 +1,57 @@
 use std::error::Error;
 use std::fmt;
+use std::io;
+use bincode;
+use snap;
+use format::FORMAT_VERSION;
 
 #[derive(Debug)]
 pub enum NuiError {
     Failed(String),
     BadType,
     PlayBackEnd,
+    NotARecording,
+    /// A recording made before the binary format, which only playback
+    /// and the edits can read.
+    LegacyRecording,
+    UnsupportedVersion(u32),
+    SeekOutOfRange(usize),
+    /// Reading or writing a recording failed.
+    Io(io::Error),
+    /// A recording could not be decoded.
+    Corrupt(String),
+    /// A frame was recorded without one of its streams.
+    MissingStream(&'static str),
+    /// A captured image doesn't have `rows * cols` pixels.
+    BadFrame(&'static str),
+    /// The other end of a capture channel has gone away.
+    Disconnected,
+    /// A recording can't be edited as asked.
+    BadEdit(String),
+    /// Data can't be exported as asked.
+    BadExport(String),
 }
 
 impl Error for NuiError {}
 
+impl From<io::Error> for NuiError {
+    fn from(e: io::Error) -> Self {
+        NuiError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for NuiError {
+    fn from(e: bincode::Error) -> Self {
+        NuiError::Corrupt(e.to_string())
+    }
+}
+
+impl From<snap::Error> for NuiError {
+    fn from(e: snap::Error) -> Self {
+        NuiError::Corrupt(e.to_string())
+    }
+}
+
 impl fmt::Display for NuiError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use NuiError::*;
@@ -17,6 +59,17 @@ impl fmt::Display for NuiError {
             Failed(msg) => write!(f, "Nui API call failed: {}", msg),
             BadType => write!(f, "A type has not conversion has failed"),
             PlayBackEnd => write!(f, "Playback has finished"),
+            NotARecording => write!(f, "File is not a nuitrack recording"),
+            LegacyRecording => write!(f, "Recording is in the legacy format, convert it with transcode_legacy or nuitrack-tool transcode"),
+            UnsupportedVersion(v) => write!(f, "Recording format version {} is not supported, expected version {}", v, FORMAT_VERSION),
+            SeekOutOfRange(frame) => write!(f, "Frame {} is past the end of the recording", frame),
+            Io(e) => write!(f, "Recording I/O failed: {}", e),
+            Corrupt(msg) => write!(f, "Recording is corrupt: {}", msg),
+            MissingStream(stream) => write!(f, "No {} data was captured for this frame", stream),
+            BadFrame(stream) => write!(f, "The captured {} frame doesn't match its size", stream),
+            Disconnected => write!(f, "Capture channel has disconnected"),
+            BadEdit(msg) => write!(f, "Can't edit recording: {}", msg),
+            BadExport(msg) => write!(f, "Can't export: {}", msg),
         }
     }
 }
@@ -0,0 +1,305 @@
+//! Skeleton motion as BVH for Blender, MotionBuilder and the like.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use data::SkeletonDataFeed;
+use edit::Frames;
+use errors::NuiError;
+use format;
+use nui::simple::SkeletonData;
+use super::hierarchy::{Hierarchy, Pose};
+use super::math::{self, Vec3};
+
+/// Nuitrack positions are in millimetres, BVH files are usually in centimetres.
+const MM_PER_UNIT: f32 = 10.0;
+
+/// Collects the motion of one skeleton and writes it as BVH.
+///
+/// The hierarchy is built from `SKELETON_BONES` with the waist as the root,
+/// and its offsets come from the first frame where every joint was tracked.
+/// Rotations come from the joint orientations, or from the directions
+/// of the bones when Nuitrack has no orientation for a joint.
+/// Frames are written every `frame_time`, each one showing
+/// the latest pose at that time.
+///
+/// ```rust,no_run
+/// # use nuitrack_rs::BvhWriter;
+/// # use std::fs::File;
+/// # use std::sync::{Arc, Mutex};
+/// # use std::time::Duration;
+/// # let mut nui = nuitrack_rs::init().expect("Couldn't initialize nuitrack");
+/// let bvh = Arc::new(Mutex::new(BvhWriter::new(1, Duration::from_micros(33_333))));
+/// let motion = bvh.clone();
+/// nui.skeleton_data(move |data| {
+///     motion.lock().unwrap().push_data(&data);
+/// }).expect("Failed to add callback");
+/// // Run nui
+/// let file = File::create("motion.bvh").expect("Couldn't create file");
+/// bvh.lock().unwrap().write(file).expect("Failed to write motion");
+/// ```
+pub struct BvhWriter {
+    skeleton_id: i32,
+    frame_time: Duration,
+    hierarchy: Hierarchy,
+    frames: Vec<(u64, Pose)>,
+}
+
+impl BvhWriter {
+    pub fn new(skeleton_id: i32, frame_time: Duration) -> Self {
+        BvhWriter{ skeleton_id, frame_time, hierarchy: Hierarchy::new(), frames: Vec::new() }
+    }
+
+    /// Adds a frame of live skeleton data.
+    pub fn push_data(&mut self, data: &SkeletonData) {
+        self.push_feed(&data.make_owned())
+    }
+
+    /// Adds a frame of recorded skeleton data.
+    /// Frames without the chosen skeleton are ignored.
+    pub fn push_feed(&mut self, data: &SkeletonDataFeed) {
+        if let Some(skeleton) = data.skeletons.iter().find(|s| s.id == self.skeleton_id) {
+            let pose = Pose::new(&self.hierarchy, skeleton);
+            self.frames.push((data.time_stamp, pose));
+        }
+    }
+
+    /// The number of frames with the chosen skeleton.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Writes the hierarchy and motion.
+    /// Returns the number of frames written.
+    pub fn write<W: Write>(&self, out: W) -> Result<usize, NuiError> {
+        let frame_micros = format::micros(self.frame_time);
+        if frame_micros == 0 {
+            return Err(NuiError::BadExport("the frame time must be above zero".to_string()));
+        }
+        let (start, end) = match (self.frames.first(), self.frames.last()) {
+            (Some(first), Some(last)) => (first.0, last.0),
+            _ => return Err(NuiError::BadExport(format!("skeleton {} was never tracked", self.skeleton_id))),
+        };
+        let rest = self.frames.iter()
+            .map(|f| &f.1)
+            .find(|p| p.is_complete())
+            .unwrap_or(&self.frames[0].1);
+
+        let mut out = BufWriter::new(out);
+        let mut order = Vec::with_capacity(self.hierarchy.len());
+        writeln!(out, "HIERARCHY")?;
+        self.write_joint(&mut out, 0, 0, rest, &mut order)?;
+
+        let count = (end.saturating_sub(start) / frame_micros + 1) as usize;
+        writeln!(out, "MOTION")?;
+        writeln!(out, "Frames: {}", count)?;
+        writeln!(out, "Frame Time: {:.6}", frame_micros as f64 * 1e-6)?;
+        let mut current = 0;
+        let mut root = rest.positions[0].unwrap_or([0.0; 3]);
+        for n in 0..count as u64 {
+            let time = start + n * frame_micros;
+            while current + 1 < self.frames.len() && self.frames[current + 1].0 <= time {
+                current += 1;
+            }
+            let pose = &self.frames[current].1;
+            if let Some(position) = pose.positions[0] {
+                root = position;
+            }
+            let rotations = self.hierarchy.rotations(rest, pose);
+            let mut values = units(root).to_vec();
+            for &i in &order {
+                let local = match self.hierarchy.parents[i] {
+                    Some(p) => math::mul(&math::transpose(&rotations[p]), &rotations[i]),
+                    None => rotations[i],
+                };
+                values.extend_from_slice(&math::euler_zxy(&local));
+            }
+            let line: Vec<String> = values.iter().map(|v| format!("{:.4}", v)).collect();
+            writeln!(out, "{}", line.join(" "))?;
+        }
+        out.flush()?;
+        Ok(count)
+    }
+
+    /// Writes a joint and its children, noting the order the channels are in.
+    fn write_joint<W: Write>(&self, out: &mut W, joint: usize, depth: usize, rest: &Pose, order: &mut Vec<usize>) -> io::Result<()> {
+        let indent = "\t".repeat(depth);
+        let name = self.hierarchy.joints[joint].name();
+        let offset = self.offset(joint, rest);
+        order.push(joint);
+        match self.hierarchy.parents[joint] {
+            None => {
+                writeln!(out, "{}ROOT {}", indent, name)?;
+                writeln!(out, "{}{{", indent)?;
+                writeln!(out, "{}\tOFFSET {}", indent, format_vec(offset))?;
+                writeln!(out, "{}\tCHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation", indent)?;
+            },
+            Some(_) => {
+                writeln!(out, "{}JOINT {}", indent, name)?;
+                writeln!(out, "{}{{", indent)?;
+                writeln!(out, "{}\tOFFSET {}", indent, format_vec(offset))?;
+                writeln!(out, "{}\tCHANNELS 3 Zrotation Xrotation Yrotation", indent)?;
+            },
+        }
+        let children = self.hierarchy.children(joint);
+        if children.is_empty() {
+            // The end site carries on the last bone at half its length
+            writeln!(out, "{}\tEnd Site", indent)?;
+            writeln!(out, "{}\t{{", indent)?;
+            writeln!(out, "{}\t\tOFFSET {}", indent, format_vec(math::scale(offset, 0.5)))?;
+            writeln!(out, "{}\t}}", indent)?;
+        }
+        for child in children {
+            self.write_joint(out, child, depth + 1, rest, order)?;
+        }
+        writeln!(out, "{}}}", indent)
+    }
+
+    /// The joint's position from its parent in the rest pose.
+    fn offset(&self, joint: usize, rest: &Pose) -> Vec3 {
+        self.hierarchy.parents[joint]
+            .and_then(|p| rest.bone(p, joint))
+            .map_or([0.0; 3], units)
+    }
+}
+
+fn units(mm: Vec3) -> Vec3 {
+    math::scale(mm, 1.0 / MM_PER_UNIT)
+}
+
+fn format_vec(v: Vec3) -> String {
+    format!("{:.4} {:.4} {:.4}", v[0], v[1], v[2])
+}
+
+/// Writes the motion of `skeleton_id` in a recording as BVH.
+/// Returns the number of frames written.
+pub fn export_bvh(recording: &Path, output: &Path, skeleton_id: i32, frame_time: Duration) -> Result<usize, NuiError> {
+    let mut bvh = BvhWriter::new(skeleton_id, frame_time);
+    for frame in Frames::open(recording)? {
+        if let Some(ref skeleton) = frame?.skeleton {
+            bvh.push_feed(skeleton);
+        }
+    }
+    bvh.write(File::create(output)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use data::SkeletonFeed;
+    use joint_type::JointType;
+    use nui::tdv::nuitrack::{Joint, Orientation, Vector3};
+    use super::*;
+
+    /// A T-pose in millimetres, with the left forearm raised if `raised`.
+    fn position(joint: JointType, raised: bool) -> Option<Vec3> {
+        let p = match joint {
+            JointType::Waist => [0.0, 1000.0, 2000.0],
+            JointType::Torso => [0.0, 1200.0, 2000.0],
+            JointType::Neck => [0.0, 1450.0, 2000.0],
+            JointType::Head => [0.0, 1650.0, 2000.0],
+            JointType::LeftShoulder => [-200.0, 1400.0, 2000.0],
+            JointType::LeftElbow => [-450.0, 1400.0, 2000.0],
+            JointType::LeftWrist if raised => [-450.0, 1650.0, 2000.0],
+            JointType::LeftWrist => [-700.0, 1400.0, 2000.0],
+            JointType::RightShoulder => [200.0, 1400.0, 2000.0],
+            JointType::RightElbow => [450.0, 1400.0, 2000.0],
+            JointType::RightWrist => [700.0, 1400.0, 2000.0],
+            JointType::LeftHip => [-100.0, 950.0, 2000.0],
+            JointType::LeftKnee => [-100.0, 500.0, 2000.0],
+            JointType::LeftAnkle => [-100.0, 80.0, 2000.0],
+            JointType::RightHip => [100.0, 950.0, 2000.0],
+            JointType::RightKnee => [100.0, 500.0, 2000.0],
+            JointType::RightAnkle => [100.0, 80.0, 2000.0],
+            _ => return None,
+        };
+        Some(p)
+    }
+
+    /// Skeleton 3 without orientations, so rotations come from the bones.
+    fn feed(time_stamp: u64, raised: bool) -> SkeletonDataFeed {
+        let joints = (1..25).filter_map(|t| {
+            let p = position(JointType::from_u32(t)?, raised)?;
+            Some(Joint{
+                type_: t,
+                confidence: 0.75,
+                real: Vector3{ x: p[0], y: p[1], z: p[2] },
+                proj: Vector3{ x: 0.0, y: 0.0, z: 0.0 },
+                orient: Orientation{ matrix: [0.0; 9] },
+            })
+        }).collect();
+        SkeletonDataFeed{ time_stamp, skeletons: vec![SkeletonFeed{ id: 3, joints }] }
+    }
+
+    fn write(bvh: &BvhWriter) -> String {
+        let mut out = Vec::new();
+        bvh.write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// The values of each frame.
+    fn motion(bvh: &str) -> Vec<Vec<f32>> {
+        bvh.lines()
+            .skip_while(|l| !l.starts_with("Frame Time"))
+            .skip(1)
+            .map(|l| l.split(' ').map(|v| v.parse().unwrap()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn hierarchy_from_the_rest_pose() {
+        let mut bvh = BvhWriter::new(3, Duration::from_millis(10));
+        bvh.push_feed(&feed(0, false));
+        let out = write(&bvh);
+        assert!(out.starts_with("HIERARCHY\nROOT waist\n{\n\tOFFSET 0.0000 0.0000 0.0000\n\tCHANNELS 6 "));
+        // Offsets are in centimetres
+        assert!(out.contains("\tJOINT torso\n\t{\n\t\tOFFSET 0.0000 20.0000 0.0000\n"));
+        assert!(out.contains("JOINT left_wrist\n\t\t\t\t\t{\n\t\t\t\t\t\tOFFSET -25.0000 0.0000 0.0000\n"));
+        assert_eq!(out.matches("JOINT ").count(), 15);
+        assert_eq!(out.matches("End Site").count(), 5);
+        assert!(out.contains("MOTION\nFrames: 1\nFrame Time: 0.010000\n"));
+        assert_eq!(motion(&out)[0][..3], [0.0, 100.0, 200.0]);
+    }
+
+    #[test]
+    fn frames_are_resampled_and_rotated_from_bones() {
+        let mut bvh = BvhWriter::new(3, Duration::from_millis(10));
+        bvh.push_feed(&feed(0, false));
+        // Frames without the skeleton are left out
+        bvh.push_feed(&SkeletonDataFeed{ time_stamp: 5_000, skeletons: Vec::new() });
+        bvh.push_feed(&feed(20_000, true));
+        assert_eq!(bvh.len(), 2);
+        let out = write(&bvh);
+        let frames = motion(&out);
+        assert_eq!(frames.len(), 3);
+        // The channels follow the joints in the order they are written
+        let joints: Vec<&str> = out.lines()
+            .map(str::trim)
+            .filter(|l| l.starts_with("ROOT ") || l.starts_with("JOINT "))
+            .map(|l| l.split(' ').nth(1).unwrap())
+            .collect();
+        let elbow = 3 + 3 * joints.iter().position(|&j| j == "left_elbow").unwrap();
+        // 10ms still shows the pose from 0ms, then the forearm turns up about z
+        assert_eq!(frames[1], frames[0]);
+        assert!(frames[0][elbow].abs() < 1e-3);
+        assert!((frames[2][elbow] + 90.0).abs() < 1e-3, "{}", frames[2][elbow]);
+        for (i, v) in frames[2].iter().enumerate().skip(3) {
+            if i != elbow {
+                assert!(v.abs() < 1e-3, "channel {} is {}", i, v);
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_to_write() {
+        let mut bvh = BvhWriter::new(4, Duration::from_millis(10));
+        bvh.push_feed(&feed(0, false));
+        match bvh.write(Vec::new()) {
+            Err(NuiError::BadExport(_)) => (),
+            other => panic!("expected BadExport, got {:?}", other),
+        }
+        let mut bvh = BvhWriter::new(3, Duration::from_millis(0));
+        bvh.push_feed(&feed(0, false));
+        assert!(bvh.write(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,295 @@
+//! Skeleton data in the C3D motion capture format.
+//!
+//! Files are little endian with float data. Every joint of every skeleton
+//! is a point labelled `skeleton<id>:<joint>`, in millimetres in Nuitrack's
+//! axes. The residual holds the joint confidence and untracked joints are
+//! marked invalid.
+//!
+//! Past 65535 frames the header's last frame is left at 65535 and the real
+//! count is in `TRIAL:ACTUAL_END_FIELD`. Past 255 points the labels carry on
+//! in `LABELS2`, `LABELS3` and so on, as do the descriptions.
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use byteorder::{LittleEndian, WriteBytesExt};
+use data::SkeletonFeed;
+use edit::Frames;
+use errors::NuiError;
+use export::joint_types;
+
+const BLOCK: usize = 512;
+
+/// The parameters start in the second block.
+const PARAMETER_BLOCK: u8 = 2;
+
+/// Intel byte order.
+const PROCESSOR_INTEL: u8 = 84;
+
+/// Residuals are stored as whole numbers from 0 to 100,
+/// readers scale them back to a 0 to 1 confidence by this.
+/// Negative as the data is float.
+const POINT_SCALE: f32 = -0.01;
+
+const POINT_GROUP: i8 = 1;
+const ANALOG_GROUP: i8 = 2;
+const TRIAL_GROUP: i8 = 3;
+
+/// Strings in one parameter, more go in the next numbered parameter.
+const STRINGS_PER_PARAMETER: usize = 255;
+
+/// The frame rate used when there are too few time stamps to measure it.
+const DEFAULT_RATE: f32 = 30.0;
+
+/// Records in the parameter section.
+struct Parameters {
+    bytes: Vec<u8>,
+    /// Where the last record's offset to the next record is, zeroed at the end.
+    last_offset: usize,
+}
+
+impl Parameters {
+    fn new() -> Self {
+        Parameters{ bytes: Vec::new(), last_offset: 0 }
+    }
+
+    fn group(&mut self, id: i8, name: &str, description: &str) {
+        self.bytes.push(name.len() as u8);
+        self.bytes.push(-id as u8);
+        self.bytes.extend_from_slice(name.as_bytes());
+        self.next_record(1 + description.len());
+        self.bytes.push(description.len() as u8);
+        self.bytes.extend_from_slice(description.as_bytes());
+    }
+
+    /// `kind` is -1 for characters, 2 for `i16` and 4 for `f32`.
+    fn parameter(&mut self, group: i8, name: &str, kind: i8, dimensions: &[u8], data: &[u8]) {
+        self.bytes.push(name.len() as u8);
+        self.bytes.push(group as u8);
+        self.bytes.extend_from_slice(name.as_bytes());
+        self.next_record(2 + dimensions.len() + data.len() + 1);
+        self.bytes.push(kind as u8);
+        self.bytes.push(dimensions.len() as u8);
+        self.bytes.extend_from_slice(dimensions);
+        self.bytes.extend_from_slice(data);
+        // No description
+        self.bytes.push(0);
+    }
+
+    fn int(&mut self, group: i8, name: &str, value: u16) {
+        let mut data = Vec::new();
+        data.write_u16::<LittleEndian>(value).expect("Writing to a Vec can't fail");
+        self.parameter(group, name, 2, &[], &data);
+    }
+
+    fn ints(&mut self, group: i8, name: &str, values: &[u16]) {
+        let mut data = Vec::new();
+        for value in values {
+            data.write_u16::<LittleEndian>(*value).expect("Writing to a Vec can't fail");
+        }
+        self.parameter(group, name, 2, &[values.len() as u8], &data);
+    }
+
+    /// A 32 bit count stored as two ints, low word first.
+    fn long(&mut self, group: i8, name: &str, value: u32) {
+        self.ints(group, name, &[value as u16, (value >> 16) as u16]);
+    }
+
+    fn float(&mut self, group: i8, name: &str, value: f32) {
+        let mut data = Vec::new();
+        data.write_f32::<LittleEndian>(value).expect("Writing to a Vec can't fail");
+        self.parameter(group, name, 4, &[], &data);
+    }
+
+    /// Strings padded with spaces to the longest.
+    fn strings(&mut self, group: i8, name: &str, values: &[String]) -> Result<(), NuiError> {
+        let width = values.iter().map(|v| v.len()).max().unwrap_or(0).max(1);
+        if width > 255 || values.len() > 255 {
+            return Err(NuiError::BadExport(format!("too many or too long {} for C3D", name.to_lowercase())));
+        }
+        let mut data = Vec::with_capacity(width * values.len());
+        for value in values {
+            data.extend_from_slice(value.as_bytes());
+            data.extend((value.len()..width).map(|_| b' '));
+        }
+        self.parameter(group, name, -1, &[width as u8, values.len() as u8], &data);
+        Ok(())
+    }
+
+    /// Strings split over `NAME`, `NAME2`, `NAME3` and so on,
+    /// 255 to a parameter.
+    fn string_sets(&mut self, group: i8, name: &str, values: &[String]) -> Result<(), NuiError> {
+        if values.is_empty() {
+            return self.strings(group, name, values);
+        }
+        for (i, set) in values.chunks(STRINGS_PER_PARAMETER).enumerate() {
+            match i {
+                0 => self.strings(group, name, set)?,
+                _ => self.strings(group, &format!("{}{}", name, i + 1), set)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the offset from here to the record after this one, `rest` bytes on.
+    fn next_record(&mut self, rest: usize) {
+        self.last_offset = self.bytes.len();
+        self.bytes.write_i16::<LittleEndian>(2 + rest as i16).expect("Writing to a Vec can't fail");
+    }
+
+    /// The section with its header, padded to whole blocks.
+    fn into_blocks(mut self) -> Result<Vec<u8>, NuiError> {
+        self.bytes[self.last_offset] = 0;
+        self.bytes[self.last_offset + 1] = 0;
+        let blocks = (4 + self.bytes.len() + BLOCK - 1) / BLOCK;
+        if blocks > u8::max_value() as usize {
+            return Err(NuiError::BadExport("too many points for the C3D parameter section".to_string()));
+        }
+        let mut section = vec![1, 0x50, blocks as u8, PROCESSOR_INTEL];
+        section.extend_from_slice(&self.bytes);
+        section.resize(blocks * BLOCK, 0);
+        Ok(section)
+    }
+}
+
+/// The frame rate of evenly spaced time stamps in microseconds.
+pub fn frame_rate(time_stamps: &[u64]) -> f32 {
+    match (time_stamps.first(), time_stamps.last()) {
+        (Some(first), Some(last)) if last > first => {
+            (time_stamps.len() - 1) as f32 / ((last - first) as f32 * 1e-6)
+        },
+        _ => DEFAULT_RATE,
+    }
+}
+
+/// Writes frames of skeletons as C3D at `frame_rate` frames per second.
+/// Returns the number of frames written.
+///
+/// ```rust,no_run
+/// # use std::fs::File;
+/// # let frames: Vec<Vec<nuitrack_rs::SkeletonFeed>> = Vec::new();
+/// let file = File::create("motion.c3d").expect("Couldn't create file");
+/// nuitrack_rs::write_c3d(file, frames, 30.0).expect("Failed to write C3D");
+/// ```
+pub fn write_c3d<W, I>(out: W, frames: I, frame_rate: f32) -> Result<usize, NuiError>
+where
+    W: Write,
+    I: IntoIterator<Item = Vec<SkeletonFeed>>,
+{
+    let frames: Vec<Vec<SkeletonFeed>> = frames.into_iter().collect();
+    if frames.len() > u32::max_value() as usize {
+        return Err(NuiError::BadExport(format!("C3D holds at most {} frames", u32::max_value())));
+    }
+    // The header and POINT:FRAMES stop here, TRIAL has the real count
+    let short_frames = frames.len().min(u16::max_value() as usize) as u16;
+    if !(frame_rate > 0.0) {
+        return Err(NuiError::BadExport("the frame rate must be above zero".to_string()));
+    }
+    let ids: BTreeSet<i32> = frames.iter().flat_map(|f| f.iter().map(|s| s.id)).collect();
+    let joints = joint_types();
+    let mut labels = Vec::new();
+    let mut descriptions = Vec::new();
+    for id in &ids {
+        for joint in &joints {
+            labels.push(format!("skeleton{}:{}", id, joint.name()));
+            descriptions.push(format!("{:?} of skeleton {}", joint, id));
+        }
+    }
+    let points = labels.len();
+    if points > i16::max_value() as usize {
+        return Err(NuiError::BadExport(format!("C3D holds at most {} points, {} skeletons were seen", i16::max_value(), ids.len())));
+    }
+
+    // The data start is a parameter too so the parameters are sized first
+    let parameters = |data_start: u16| -> Result<Vec<u8>, NuiError> {
+        let mut p = Parameters::new();
+        p.group(POINT_GROUP, "POINT", "3D point parameters");
+        p.int(POINT_GROUP, "USED", points as u16);
+        p.int(POINT_GROUP, "FRAMES", short_frames);
+        p.int(POINT_GROUP, "DATA_START", data_start);
+        p.float(POINT_GROUP, "SCALE", POINT_SCALE);
+        p.float(POINT_GROUP, "RATE", frame_rate);
+        p.string_sets(POINT_GROUP, "LABELS", &labels)?;
+        p.string_sets(POINT_GROUP, "DESCRIPTIONS", &descriptions)?;
+        p.strings(POINT_GROUP, "UNITS", &["mm".to_string()])?;
+        p.group(ANALOG_GROUP, "ANALOG", "Analog data parameters");
+        p.int(ANALOG_GROUP, "USED", 0);
+        p.float(ANALOG_GROUP, "RATE", frame_rate);
+        p.group(TRIAL_GROUP, "TRIAL", "Trial parameters");
+        p.long(TRIAL_GROUP, "ACTUAL_START_FIELD", 1);
+        p.long(TRIAL_GROUP, "ACTUAL_END_FIELD", frames.len() as u32);
+        p.into_blocks()
+    };
+    let blocks = parameters(0)?.len() / BLOCK;
+    let data_start = PARAMETER_BLOCK as usize + blocks;
+    let parameters = parameters(data_start as u16)?;
+
+    let mut header = Vec::with_capacity(BLOCK);
+    header.push(PARAMETER_BLOCK);
+    header.push(0x50);
+    header.write_u16::<LittleEndian>(points as u16)?;
+    // Analog channels
+    header.write_u16::<LittleEndian>(0)?;
+    // First and last frame
+    header.write_u16::<LittleEndian>(1)?;
+    header.write_u16::<LittleEndian>(short_frames)?;
+    // Largest gap to interpolate
+    header.write_u16::<LittleEndian>(10)?;
+    header.write_f32::<LittleEndian>(POINT_SCALE)?;
+    header.write_u16::<LittleEndian>(data_start as u16)?;
+    // Analog samples per frame
+    header.write_u16::<LittleEndian>(0)?;
+    header.write_f32::<LittleEndian>(frame_rate)?;
+    header.resize(BLOCK, 0);
+
+    let mut out = BufWriter::new(out);
+    out.write_all(&header)?;
+    out.write_all(&parameters)?;
+    let mut written = 0;
+    for frame in &frames {
+        for id in &ids {
+            let skeleton = frame.iter().find(|s| s.id == *id);
+            for joint in &joints {
+                let tracked = skeleton
+                    .and_then(|s| s.joints.iter().find(|j| j.type_ == *joint as u32))
+                    .filter(|j| j.confidence > 0.0);
+                match tracked {
+                    Some(j) => {
+                        out.write_f32::<LittleEndian>(j.real.x)?;
+                        out.write_f32::<LittleEndian>(j.real.y)?;
+                        out.write_f32::<LittleEndian>(j.real.z)?;
+                        // Seen by one camera with the confidence as the residual
+                        let residual = (j.confidence.min(1.0) * 100.0).round() as u16;
+                        out.write_f32::<LittleEndian>((1 << 8 | residual) as f32)?;
+                    },
+                    None => {
+                        for v in &[0.0, 0.0, 0.0, -1.0] {
+                            out.write_f32::<LittleEndian>(*v)?;
+                        }
+                    },
+                }
+                written += 16;
+            }
+        }
+    }
+    // Fill the last block
+    let padding = (BLOCK - written % BLOCK) % BLOCK;
+    out.write_all(&vec![0; padding])?;
+    out.flush()?;
+    Ok(frames.len())
+}
+
+/// Writes the skeletons of a recording as C3D, with the frame rate
+/// measured from the time stamps. Returns the number of frames written.
+pub fn export_c3d(recording: &Path, output: &Path) -> Result<usize, NuiError> {
+    let mut time_stamps = Vec::new();
+    let mut frames = Vec::new();
+    for frame in Frames::open(recording)? {
+        if let Some(skeleton) = frame?.skeleton {
+            time_stamps.push(skeleton.time_stamp);
+            frames.push(skeleton.skeletons);
+        }
+    }
+    write_c3d(File::create(output)?, frames, frame_rate(&time_stamps))
+}
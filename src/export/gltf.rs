@@ -0,0 +1,458 @@
+//! Skeleton animation as binary glTF 2.0 for three.js and the like.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use byteorder::{LittleEndian, WriteBytesExt};
+use serde_json;
+use data::SkeletonDataFeed;
+use edit::Frames;
+use errors::NuiError;
+use nui::simple::SkeletonData;
+use super::hierarchy::{Hierarchy, Pose};
+use super::math::{self, Vec3};
+
+/// Nuitrack positions are in millimetres, glTF is in metres.
+const MM_PER_METRE: f32 = 1000.0;
+
+/// `FLOAT` in glTF accessors.
+const FLOAT: u32 = 5126;
+
+const GLB_MAGIC: &'static [u8; 4] = b"glTF";
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+/// Collects the motion of every skeleton and writes it as a glTF skin.
+///
+/// The joint nodes are built from `SKELETON_BONES` with the waist as the root
+/// and rest at the first pose where every joint was tracked.
+/// Each skeleton id gets its own animation, with translations from
+/// `Joint::real` and rotations from `Joint::orient`. Rotations come from
+/// the bone directions when Nuitrack has no orientation for a joint.
+///
+/// ```rust,no_run
+/// # use nuitrack_rs::GltfWriter;
+/// # use std::fs::File;
+/// # use std::sync::{Arc, Mutex};
+/// # let mut nui = nuitrack_rs::init().expect("Couldn't initialize nuitrack");
+/// let gltf = Arc::new(Mutex::new(GltfWriter::new()));
+/// let motion = gltf.clone();
+/// nui.skeleton_data(move |data| {
+///     motion.lock().unwrap().push_data(&data);
+/// }).expect("Failed to add callback");
+/// // Run nui
+/// let file = File::create("motion.glb").expect("Couldn't create file");
+/// gltf.lock().unwrap().write(file).expect("Failed to write motion");
+/// ```
+pub struct GltfWriter {
+    hierarchy: Hierarchy,
+    /// The earliest time stamp seen, animations start from here.
+    start: Option<u64>,
+    tracks: BTreeMap<i32, Vec<(u64, Pose)>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Document {
+    asset: Asset,
+    scene: usize,
+    scenes: Vec<Scene>,
+    nodes: Vec<Node>,
+    skins: Vec<Skin>,
+    animations: Vec<Animation>,
+    buffers: Vec<Buffer>,
+    buffer_views: Vec<BufferView>,
+    accessors: Vec<Accessor>,
+}
+
+#[derive(Serialize)]
+struct Asset {
+    version: &'static str,
+    generator: String,
+}
+
+#[derive(Serialize)]
+struct Scene {
+    nodes: Vec<usize>,
+}
+
+#[derive(Serialize)]
+struct Node {
+    name: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<usize>,
+    translation: Vec3,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Skin {
+    joints: Vec<usize>,
+    inverse_bind_matrices: usize,
+    skeleton: usize,
+}
+
+#[derive(Serialize)]
+struct Animation {
+    name: String,
+    channels: Vec<Channel>,
+    samplers: Vec<Sampler>,
+}
+
+#[derive(Serialize)]
+struct Channel {
+    sampler: usize,
+    target: Target,
+}
+
+#[derive(Serialize)]
+struct Target {
+    node: usize,
+    path: &'static str,
+}
+
+#[derive(Serialize)]
+struct Sampler {
+    input: usize,
+    output: usize,
+    interpolation: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Buffer {
+    byte_length: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferView {
+    buffer: usize,
+    byte_offset: usize,
+    byte_length: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Accessor {
+    buffer_view: usize,
+    component_type: u32,
+    count: usize,
+    #[serde(rename = "type")]
+    type_: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<Vec<f32>>,
+}
+
+/// The binary chunk and the views and accessors into it.
+struct Bin {
+    data: Vec<u8>,
+    views: Vec<BufferView>,
+    accessors: Vec<Accessor>,
+}
+
+impl Bin {
+    /// Adds floats grouped as `type_`, such as `"VEC3"`, and returns the accessor.
+    fn push(&mut self, values: &[f32], type_: &'static str, width: usize, bounds: bool) -> usize {
+        let byte_offset = self.data.len();
+        for v in values {
+            self.data.write_f32::<LittleEndian>(*v).expect("Writing to a Vec can't fail");
+        }
+        self.views.push(BufferView{ buffer: 0, byte_offset, byte_length: values.len() * 4 });
+        // Only single floats need bounds here, for animation times
+        let (min, max) = if bounds {
+            let min = values.iter().cloned().fold(::std::f32::INFINITY, f32::min);
+            let max = values.iter().cloned().fold(::std::f32::NEG_INFINITY, f32::max);
+            (Some(vec![min]), Some(vec![max]))
+        } else {
+            (None, None)
+        };
+        self.accessors.push(Accessor{
+            buffer_view: self.views.len() - 1,
+            component_type: FLOAT,
+            count: values.len() / width,
+            type_,
+            min,
+            max,
+        });
+        self.accessors.len() - 1
+    }
+}
+
+impl GltfWriter {
+    pub fn new() -> Self {
+        GltfWriter{ hierarchy: Hierarchy::new(), start: None, tracks: BTreeMap::new() }
+    }
+
+    /// Adds a frame of live skeleton data.
+    pub fn push_data(&mut self, data: &SkeletonData) {
+        self.push_feed(&data.make_owned())
+    }
+
+    /// Adds a frame of recorded skeleton data.
+    pub fn push_feed(&mut self, data: &SkeletonDataFeed) {
+        self.start = Some(self.start.map_or(data.time_stamp, |start| start.min(data.time_stamp)));
+        for skeleton in &data.skeletons {
+            let track = self.tracks.entry(skeleton.id).or_insert_with(Vec::new);
+            // Key frame times must increase
+            if track.last().map_or(true, |&(t, _)| data.time_stamp > t) {
+                track.push((data.time_stamp, Pose::new(&self.hierarchy, skeleton)));
+            }
+        }
+    }
+
+    /// The ids of the skeletons seen so far.
+    pub fn skeleton_ids(&self) -> Vec<i32> {
+        self.tracks.keys().cloned().collect()
+    }
+
+    /// Writes the nodes, skin and animations as a `.glb` file.
+    /// Returns the number of animations written.
+    pub fn write<W: Write>(&self, out: W) -> Result<usize, NuiError> {
+        let start = match self.start {
+            Some(start) if !self.tracks.is_empty() => start,
+            _ => return Err(NuiError::BadExport("no skeletons were tracked".to_string())),
+        };
+        let rest = self.tracks.values()
+            .flat_map(|t| t.iter().map(|f| &f.1))
+            .find(|p| p.is_complete())
+            .unwrap_or_else(|| &self.tracks.values().next().expect("Checked above")[0].1);
+
+        let h = &self.hierarchy;
+        // The rest pose has no rotations so joints are placed by translation alone
+        let mut locals = Vec::with_capacity(h.len());
+        let mut globals: Vec<Vec3> = Vec::with_capacity(h.len());
+        for i in 0..h.len() {
+            let local = match h.parents[i] {
+                Some(p) => rest.bone(p, i).map_or([0.0; 3], metres),
+                None => rest.positions[i].map_or([0.0; 3], metres),
+            };
+            let global = h.parents[i].map_or(local, |p| add(globals[p], local));
+            locals.push(local);
+            globals.push(global);
+        }
+        let nodes = (0..h.len())
+            .map(|i| Node{ name: h.joints[i].name(), children: h.children(i), translation: locals[i] })
+            .collect();
+
+        let mut bin = Bin{ data: Vec::new(), views: Vec::new(), accessors: Vec::new() };
+        let inverse_binds: Vec<f32> = globals.iter()
+            .flat_map(|g| vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, -g[0], -g[1], -g[2], 1.0])
+            .collect();
+        let inverse_bind_matrices = bin.push(&inverse_binds, "MAT4", 16, false);
+        let skin = Skin{ joints: (0..h.len()).collect(), inverse_bind_matrices, skeleton: 0 };
+
+        let animations = self.tracks.iter()
+            .map(|(id, track)| self.animation(*id, track, start, rest, &locals, &mut bin))
+            .collect();
+
+        let document = Document{
+            asset: Asset{ version: "2.0", generator: format!("nuitrack-rs {}", env!("CARGO_PKG_VERSION")) },
+            scene: 0,
+            scenes: vec![Scene{ nodes: vec![0] }],
+            nodes,
+            skins: vec![skin],
+            animations,
+            buffers: vec![Buffer{ byte_length: bin.data.len() }],
+            buffer_views: bin.views,
+            accessors: bin.accessors,
+        };
+        let json = serde_json::to_vec(&document).map_err(|e| NuiError::Io(e.into()))?;
+        write_glb(out, json, bin.data)?;
+        Ok(self.tracks.len())
+    }
+
+    fn animation(&self, id: i32, track: &[(u64, Pose)], start: u64, rest: &Pose, rest_locals: &[Vec3], bin: &mut Bin) -> Animation {
+        let h = &self.hierarchy;
+        let times: Vec<f32> = track.iter().map(|&(t, _)| t.saturating_sub(start) as f32 * 1e-6).collect();
+        let mut translations = vec![Vec::with_capacity(track.len() * 3); h.len()];
+        let mut rotations = vec![Vec::with_capacity(track.len() * 4); h.len()];
+        let mut root = rest_locals[0];
+        for &(_, ref pose) in track {
+            let globals = h.rotations(rest, pose);
+            if let Some(p) = pose.positions[0] {
+                root = metres(p);
+            }
+            for i in 0..h.len() {
+                let (translation, local) = match h.parents[i] {
+                    Some(p) => {
+                        let inverse = math::transpose(&globals[p]);
+                        let translation = pose.bone(p, i)
+                            .map_or(rest_locals[i], |b| math::mul_vec(&inverse, metres(b)));
+                        (translation, math::mul(&inverse, &globals[i]))
+                    },
+                    None => (root, globals[i]),
+                };
+                let mut q = math::quaternion(&local);
+                // Keep to the same hemisphere as the last key so the rotation doesn't spin the long way round
+                let n = rotations[i].len();
+                if n >= 4 && (0..4).map(|k| q[k] * rotations[i][n - 4 + k]).sum::<f32>() < 0.0 {
+                    q = [-q[0], -q[1], -q[2], -q[3]];
+                }
+                translations[i].extend_from_slice(&translation);
+                rotations[i].extend_from_slice(&q);
+            }
+        }
+
+        let input = bin.push(&times, "SCALAR", 1, true);
+        let mut channels = Vec::new();
+        let mut samplers = Vec::new();
+        for i in 0..h.len() {
+            for &(path, ref values, type_, width) in &[
+                ("translation", &translations[i], "VEC3", 3),
+                ("rotation", &rotations[i], "VEC4", 4),
+            ] {
+                let output = bin.push(values, type_, width, false);
+                channels.push(Channel{ sampler: samplers.len(), target: Target{ node: i, path } });
+                samplers.push(Sampler{ input, output, interpolation: "LINEAR" });
+            }
+        }
+        Animation{ name: format!("skeleton {}", id), channels, samplers }
+    }
+}
+
+fn metres(mm: Vec3) -> Vec3 {
+    math::scale(mm, 1.0 / MM_PER_METRE)
+}
+
+fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+/// Writes the binary container, with each chunk padded to 4 bytes.
+fn write_glb<W: Write>(out: W, mut json: Vec<u8>, mut bin: Vec<u8>) -> Result<(), NuiError> {
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+    while bin.len() % 4 != 0 {
+        bin.push(0);
+    }
+    let mut out = BufWriter::new(out);
+    out.write_all(GLB_MAGIC)?;
+    out.write_u32::<LittleEndian>(2)?;
+    out.write_u32::<LittleEndian>((12 + 8 + json.len() + 8 + bin.len()) as u32)?;
+    out.write_u32::<LittleEndian>(json.len() as u32)?;
+    out.write_u32::<LittleEndian>(CHUNK_JSON)?;
+    out.write_all(&json)?;
+    out.write_u32::<LittleEndian>(bin.len() as u32)?;
+    out.write_u32::<LittleEndian>(CHUNK_BIN)?;
+    out.write_all(&bin)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes the motion of every skeleton in a recording as a `.glb` file.
+/// Returns the number of animations written.
+pub fn export_gltf(recording: &Path, output: &Path) -> Result<usize, NuiError> {
+    let mut gltf = GltfWriter::new();
+    for frame in Frames::open(recording)? {
+        if let Some(ref skeleton) = frame?.skeleton {
+            gltf.push_feed(skeleton);
+        }
+    }
+    gltf.write(File::create(output)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::ByteOrder;
+    use serde_json::Value;
+    use data::SkeletonFeed;
+    use nui::tdv::nuitrack::{Joint, Orientation, Vector3};
+    use super::*;
+
+    /// Skeletons that only have their waist, at `x` millimetres.
+    fn feed(time_stamp: u64, ids: &[i32], x: f32) -> SkeletonDataFeed {
+        let waist = Joint{
+            type_: 4,
+            confidence: 0.75,
+            real: Vector3{ x, y: 1000.0, z: 2000.0 },
+            proj: Vector3{ x: 0.0, y: 0.0, z: 0.0 },
+            orient: Orientation{ matrix: [0.0; 9] },
+        };
+        let skeletons = ids.iter().map(|&id| SkeletonFeed{ id, joints: vec![waist.clone()] }).collect();
+        SkeletonDataFeed{ time_stamp, skeletons }
+    }
+
+    /// The JSON and binary chunks of a `.glb` file.
+    fn read_glb(gltf: &GltfWriter) -> (Value, Vec<u8>) {
+        let mut glb = Vec::new();
+        gltf.write(&mut glb).unwrap();
+        assert_eq!(&glb[..4], GLB_MAGIC);
+        assert_eq!(LittleEndian::read_u32(&glb[4..8]), 2);
+        assert_eq!(LittleEndian::read_u32(&glb[8..12]) as usize, glb.len());
+        let json_len = LittleEndian::read_u32(&glb[12..16]) as usize;
+        assert_eq!(LittleEndian::read_u32(&glb[16..20]), CHUNK_JSON);
+        let json = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+        let bin = glb[20 + json_len + 8..].to_vec();
+        (json, bin)
+    }
+
+    /// The floats behind an accessor.
+    fn floats(json: &Value, bin: &[u8], accessor: &Value) -> Vec<f32> {
+        let accessor = &json["accessors"][accessor.as_u64().unwrap() as usize];
+        let view = &json["bufferViews"][accessor["bufferView"].as_u64().unwrap() as usize];
+        let offset = view["byteOffset"].as_u64().unwrap() as usize;
+        let len = view["byteLength"].as_u64().unwrap() as usize;
+        bin[offset..offset + len].chunks(4).map(LittleEndian::read_f32).collect()
+    }
+
+    #[test]
+    fn one_animation_per_skeleton() {
+        let mut gltf = GltfWriter::new();
+        gltf.push_feed(&feed(0, &[1, 2], 0.0));
+        gltf.push_feed(&feed(10_000, &[1], 500.0));
+        assert_eq!(gltf.skeleton_ids(), vec![1, 2]);
+        let (json, bin) = read_glb(&gltf);
+        assert_eq!(json["asset"]["version"], "2.0");
+        let nodes = json["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), Hierarchy::new().len());
+        assert_eq!(nodes[0]["name"], "waist");
+        let translation: Vec<f64> = (0..3).map(|i| nodes[0]["translation"][i].as_f64().unwrap()).collect();
+        assert!((translation[1] - 1.0).abs() < 1e-6 && (translation[2] - 2.0).abs() < 1e-6, "{:?}", translation);
+        assert_eq!(json["skins"][0]["joints"].as_array().unwrap().len(), nodes.len());
+        let animations = json["animations"].as_array().unwrap();
+        assert_eq!(animations.len(), 2);
+        assert_eq!(animations[0]["name"], "skeleton 1");
+        // A translation and a rotation for every joint
+        assert_eq!(animations[0]["channels"].as_array().unwrap().len(), 2 * nodes.len());
+        let root = &animations[0]["samplers"][0];
+        assert_eq!(floats(&json, &bin, &root["input"]), vec![0.0, 0.01]);
+        assert_eq!(floats(&json, &bin, &root["output"]), vec![0.0, 1.0, 2.0, 0.5, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn key_frames_start_at_the_earliest_frame() {
+        let mut gltf = GltfWriter::new();
+        gltf.push_feed(&feed(100_000, &[1], 0.0));
+        // An earlier frame moves the start back instead of underflowing
+        gltf.push_feed(&feed(50_000, &[2], 0.0));
+        gltf.push_feed(&feed(200_000, &[1], 0.0));
+        // Key frame times must increase so a repeated time stamp is left out
+        gltf.push_feed(&feed(200_000, &[1], 0.0));
+        let (json, bin) = read_glb(&gltf);
+        let times = |animation: usize| {
+            let input = &json["animations"][animation]["samplers"][0]["input"];
+            let accessor = &json["accessors"][input.as_u64().unwrap() as usize];
+            (floats(&json, &bin, input), accessor["min"][0].as_f64(), accessor["max"][0].as_f64())
+        };
+        let (one, min, max) = times(0);
+        assert_eq!(one, vec![0.05, 0.15]);
+        assert_eq!((min, max), (Some(0.05), Some(0.15)));
+        assert_eq!(times(1).0, vec![0.0]);
+    }
+
+    #[test]
+    fn nothing_to_write() {
+        match GltfWriter::new().write(Vec::new()) {
+            Err(NuiError::BadExport(_)) => (),
+            other => panic!("expected BadExport, got {:?}", other),
+        }
+        let mut gltf = GltfWriter::new();
+        gltf.push_feed(&SkeletonDataFeed{ time_stamp: 0, skeletons: Vec::new() });
+        assert!(gltf.write(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+//! The joints of `SKELETON_BONES` as a tree, for formats that animate a rig.
+
+use std::collections::VecDeque;
+use data::SkeletonFeed;
+use joint_type::{JointType, SKELETON_BONES};
+use super::math::{self, Mat3, Vec3};
+
+/// The root of the tree.
+const ROOT: JointType = JointType::Waist;
+
+/// `SKELETON_BONES` only joins the shoulders to each other,
+/// so they hang from the neck.
+const EXTRA_BONES: &'static [(JointType, JointType)] = &[
+    (JointType::Neck, JointType::LeftShoulder),
+    (JointType::Neck, JointType::RightShoulder),
+];
+
+/// Joints in breadth first order so parents come before their children.
+pub struct Hierarchy {
+    pub joints: Vec<JointType>,
+    pub parents: Vec<Option<usize>>,
+}
+
+impl Hierarchy {
+    pub fn new() -> Self {
+        let bones: Vec<(JointType, JointType)> = SKELETON_BONES.iter().chain(EXTRA_BONES).cloned().collect();
+        let mut joints = vec![ROOT];
+        let mut parents = vec![None];
+        let mut queue = VecDeque::new();
+        queue.push_back(0);
+        while let Some(i) = queue.pop_front() {
+            let joint = joints[i];
+            for &(a, b) in &bones {
+                let next = if a == joint { b } else if b == joint { a } else { continue };
+                if !joints.contains(&next) {
+                    joints.push(next);
+                    parents.push(Some(i));
+                    queue.push_back(joints.len() - 1);
+                }
+            }
+        }
+        Hierarchy{ joints, parents }
+    }
+
+    pub fn len(&self) -> usize {
+        self.joints.len()
+    }
+
+    pub fn children(&self, joint: usize) -> Vec<usize> {
+        (0..self.len()).filter(|&i| self.parents[i] == Some(joint)).collect()
+    }
+
+    /// The global rotation of each joint from `rest` to `pose`.
+    ///
+    /// A joint's own orientation is used when both poses have it.
+    /// Otherwise the rotation is found from the directions of its bones,
+    /// and joints with no tracked bones follow their parent.
+    pub fn rotations(&self, rest: &Pose, pose: &Pose) -> Vec<Mat3> {
+        let mut rotations: Vec<Mat3> = Vec::with_capacity(self.len());
+        for i in 0..self.len() {
+            let inherited = self.parents[i].map_or(math::IDENTITY, |p| rotations[p]);
+            let rotation = match (rest.orientations[i], pose.orientations[i]) {
+                (Some(from), Some(to)) => math::mul(&to, &math::transpose(&from)),
+                _ => self.bone_rotation(i, rest, pose).unwrap_or(inherited),
+            };
+            rotations.push(rotation);
+        }
+        rotations
+    }
+
+    fn bone_rotation(&self, joint: usize, rest: &Pose, pose: &Pose) -> Option<Mat3> {
+        let bones: Vec<(Vec3, Vec3)> = self.children(joint).into_iter()
+            .filter_map(|c| Some((rest.bone(joint, c)?, pose.bone(joint, c)?)))
+            .collect();
+        match bones.len() {
+            0 => None,
+            1 => Some(math::arc(bones[0].0, bones[0].1)),
+            _ => {
+                let from = math::basis(bones[0].0, bones[1].0);
+                let to = math::basis(bones[0].1, bones[1].1);
+                match (from, to) {
+                    (Some(from), Some(to)) => Some(math::mul(&to, &math::transpose(&from))),
+                    _ => Some(math::arc(bones[0].0, bones[0].1)),
+                }
+            },
+        }
+    }
+}
+
+/// One skeleton's joints in hierarchy order.
+/// Joints that weren't tracked are None.
+#[derive(Clone, Debug)]
+pub struct Pose {
+    pub positions: Vec<Option<Vec3>>,
+    pub orientations: Vec<Option<Mat3>>,
+}
+
+impl Pose {
+    pub fn new(hierarchy: &Hierarchy, skeleton: &SkeletonFeed) -> Self {
+        let mut positions = vec![None; hierarchy.len()];
+        let mut orientations = vec![None; hierarchy.len()];
+        for joint in &skeleton.joints {
+            let i = match JointType::from_u32(joint.type_).and_then(|t| hierarchy.joints.iter().position(|j| *j == t)) {
+                Some(i) => i,
+                None => continue,
+            };
+            if joint.confidence > 0.0 {
+                positions[i] = Some([joint.real.x, joint.real.y, joint.real.z]);
+            }
+            if math::is_rotation(&joint.orient.matrix) {
+                orientations[i] = Some(joint.orient.matrix);
+            }
+        }
+        Pose{ positions, orientations }
+    }
+
+    /// True if every joint was tracked.
+    pub fn is_complete(&self) -> bool {
+        self.positions.iter().all(Option::is_some)
+    }
+
+    /// The vector from `parent` to `child`.
+    pub fn bone(&self, parent: usize, child: usize) -> Option<Vec3> {
+        Some(math::sub(self.positions[child]?, self.positions[parent]?))
+    }
+}
@@ -0,0 +1,242 @@
+//! Depth and color frames as image files.
+//!
+//! Depth keeps the raw millimetre values as 16 bit grey.
+//! Color is written as RGB, `Color3` is stored blue first.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use image::ColorType;
+use image::png::PNGEncoder;
+use data::{DepthFrameFeed, RGBFrameFeed};
+use edit::Frames;
+use errors::NuiError;
+use nui::simple::{DepthFrame, RGBFrame};
+use nui::tdv::nuitrack::Color3;
+
+/// The file format for frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// PGM for depth and PPM for color.
+    Pnm,
+    Png,
+}
+
+/// How the files of an image sequence are named.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileNaming {
+    /// The frame's position in the recording, such as `depth-000042.png`.
+    Index,
+    /// The frame's time stamp in microseconds, such as `depth-1500000.png`.
+    TimeStamp,
+}
+
+impl ImageFormat {
+    fn extension(&self, depth: bool) -> &'static str {
+        match (self, depth) {
+            (ImageFormat::Pnm, true) => "pgm",
+            (ImageFormat::Pnm, false) => "ppm",
+            (ImageFormat::Png, _) => "png",
+        }
+    }
+}
+
+/// Writes a depth image.
+pub fn write_depth<W: Write>(mut out: W, rows: i32, cols: i32, depth: &[u16], format: ImageFormat) -> Result<(), NuiError> {
+    if (rows * cols) as usize != depth.len() {
+        return Err(NuiError::BadFrame("depth"));
+    }
+    // Both formats store 16 bit samples big endian
+    let mut bytes = Vec::with_capacity(depth.len() * 2);
+    for d in depth {
+        bytes.push((d >> 8) as u8);
+        bytes.push(*d as u8);
+    }
+    match format {
+        ImageFormat::Pnm => {
+            write!(out, "P5\n{} {}\n65535\n", cols, rows)?;
+            out.write_all(&bytes)?;
+        },
+        ImageFormat::Png => PNGEncoder::new(&mut out).encode(&bytes, cols as u32, rows as u32, ColorType::Gray(16))?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes a color image.
+pub fn write_color<W: Write>(mut out: W, rows: i32, cols: i32, color: &[Color3], format: ImageFormat) -> Result<(), NuiError> {
+    if (rows * cols) as usize != color.len() {
+        return Err(NuiError::BadFrame("color"));
+    }
+    let mut bytes = Vec::with_capacity(color.len() * 3);
+    for c in color {
+        bytes.extend_from_slice(&[c.red, c.green, c.blue]);
+    }
+    match format {
+        ImageFormat::Pnm => {
+            write!(out, "P6\n{} {}\n255\n", cols, rows)?;
+            out.write_all(&bytes)?;
+        },
+        ImageFormat::Png => PNGEncoder::new(&mut out).encode(&bytes, cols as u32, rows as u32, ColorType::RGB(8))?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>, NuiError> {
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+impl DepthFrame {
+    /// Saves the frame as 16 bit grey with the raw millimetre values.
+    pub fn save_image<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> Result<(), NuiError> {
+        write_depth(create(path.as_ref())?, self.rows, self.cols, self.frame(), format)
+    }
+}
+
+impl DepthFrameFeed {
+    /// Saves the frame as 16 bit grey with the raw millimetre values.
+    pub fn save_image<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> Result<(), NuiError> {
+        write_depth(create(path.as_ref())?, self.rows, self.cols, &self.data, format)
+    }
+}
+
+impl RGBFrame {
+    /// Saves the frame as 8 bit RGB.
+    pub fn save_image<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> Result<(), NuiError> {
+        write_color(create(path.as_ref())?, self.rows, self.cols, self.frame(), format)
+    }
+}
+
+impl RGBFrameFeed {
+    /// Saves the frame as 8 bit RGB.
+    pub fn save_image<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> Result<(), NuiError> {
+        write_color(create(path.as_ref())?, self.rows, self.cols, &self.data, format)
+    }
+}
+
+/// Saves the depth and color frames of a recording into `dir`
+/// as `depth-<name>` and `color-<name>` files.
+/// Returns the number of images written.
+pub fn export_images(recording: &Path, dir: &Path, format: ImageFormat, naming: FileNaming) -> Result<usize, NuiError> {
+    fs::create_dir_all(dir)?;
+    let name = |stream: &str, index: usize, time_stamp: u64, depth: bool| {
+        let file = match naming {
+            FileNaming::Index => format!("{}-{:06}.{}", stream, index, format.extension(depth)),
+            FileNaming::TimeStamp => format!("{}-{}.{}", stream, time_stamp, format.extension(depth)),
+        };
+        dir.join(file)
+    };
+    let mut written = 0;
+    for (index, frame) in Frames::open(recording)?.enumerate() {
+        let frame = frame?;
+        if let Some(ref depth) = frame.depth {
+            depth.save_image(name("depth", index, depth.stamp.time_stamp, true), format)?;
+            written += 1;
+        }
+        if let Some(ref color) = frame.color {
+            color.save_image(name("color", index, color.stamp.time_stamp, false), format)?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+    use image;
+    use data::FrameStamp;
+    use recorder::{Recorder, TimePoint};
+    use format::Stream;
+    use super::*;
+
+    const DEPTH: [u16; 6] = [0, 1, 255, 256, 4500, u16::max_value()];
+
+    fn colors() -> Vec<Color3> {
+        vec![
+            Color3{ red: 255, green: 0, blue: 0 },
+            Color3{ red: 0, green: 255, blue: 0 },
+            Color3{ red: 0, green: 0, blue: 255 },
+            Color3{ red: 1, green: 2, blue: 3 },
+        ]
+    }
+
+    #[test]
+    fn depth_keeps_raw_values() {
+        let mut pgm = Vec::new();
+        write_depth(&mut pgm, 2, 3, &DEPTH, ImageFormat::Pnm).unwrap();
+        let header = b"P5\n3 2\n65535\n";
+        assert_eq!(&pgm[..header.len()], &header[..]);
+        assert_eq!(&pgm[header.len()..], &[0, 0, 0, 1, 0, 255, 1, 0, 17, 148, 255, 255][..]);
+
+        let mut png = Vec::new();
+        write_depth(&mut png, 2, 3, &DEPTH, ImageFormat::Png).unwrap();
+        // The PNG is 3x2 grey with 16 bit samples
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..26], &[0, 0, 0, 3, 0, 0, 0, 2, 16, 0][..]);
+    }
+
+    #[test]
+    fn color_is_written_red_first() {
+        let mut ppm = Vec::new();
+        write_color(&mut ppm, 2, 2, &colors(), ImageFormat::Pnm).unwrap();
+        let header = b"P6\n2 2\n255\n";
+        assert_eq!(&ppm[..header.len()], &header[..]);
+        assert_eq!(&ppm[header.len()..], &[255, 0, 0, 0, 255, 0, 0, 0, 255, 1, 2, 3][..]);
+
+        let mut png = Vec::new();
+        write_color(&mut png, 2, 2, &colors(), ImageFormat::Png).unwrap();
+        let decoded = image::load_from_memory(&png).unwrap().to_rgb();
+        assert_eq!(decoded.dimensions(), (2, 2));
+        assert_eq!(decoded.into_raw(), &ppm[header.len()..]);
+    }
+
+    #[test]
+    fn size_must_match_the_frame() {
+        match write_depth(Vec::new(), 3, 3, &DEPTH, ImageFormat::Png) {
+            Err(NuiError::BadFrame("depth")) => (),
+            other => panic!("expected BadFrame, got {:?}", other),
+        }
+        match write_color(Vec::new(), 1, 3, &colors(), ImageFormat::Pnm) {
+            Err(NuiError::BadFrame("color")) => (),
+            other => panic!("expected BadFrame, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn export_names_files_by_index_or_time_stamp() {
+        let base = env::temp_dir().join(format!("nuitrack-rs-images-{}", process::id()));
+        let recording = base.with_extension("snap");
+        let mut recorder = Recorder::builder()
+            .path(&recording)
+            .streams(&[Stream::Depth, Stream::Color])
+            .build().unwrap();
+        for i in 0..2 {
+            let stamp = FrameStamp{ id: i, time_stamp: 1_000_000 + i * 33_000 };
+            recorder.push_frame(TimePoint{
+                depth: Some(DepthFrameFeed{ rows: 2, cols: 3, stamp, data: DEPTH.to_vec() }),
+                color: Some(RGBFrameFeed{ rows: 2, cols: 3, stamp, data: colors().into_iter().cycle().take(6).collect() }),
+                ..TimePoint::default()
+            }).unwrap();
+        }
+        recorder.flush().unwrap();
+
+        let names = |dir: &Path| {
+            let mut names: Vec<String> = fs::read_dir(dir).unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        let dir = base.join("index");
+        assert_eq!(export_images(&recording, &dir, ImageFormat::Pnm, FileNaming::Index).unwrap(), 4);
+        assert_eq!(names(&dir), ["color-000000.ppm", "color-000001.ppm", "depth-000000.pgm", "depth-000001.pgm"]);
+        let dir = base.join("time");
+        assert_eq!(export_images(&recording, &dir, ImageFormat::Png, FileNaming::TimeStamp).unwrap(), 4);
+        assert_eq!(names(&dir), ["color-1000000.png", "color-1033000.png", "depth-1000000.png", "depth-1033000.png"]);
+        fs::remove_dir_all(base).ok();
+        fs::remove_file(recording).ok();
+    }
+}
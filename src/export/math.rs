@@ -0,0 +1,150 @@
+//! The little vector and rotation math the exporters need.
+//! Matrices are row major, like `Orientation::matrix`.
+
+pub type Vec3 = [f32; 3];
+pub type Mat3 = [f32; 9];
+
+pub const IDENTITY: Mat3 = [
+    1.0, 0.0, 0.0,
+    0.0, 1.0, 0.0,
+    0.0, 0.0, 1.0,
+];
+
+pub fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn scale(a: Vec3, s: f32) -> Vec3 {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+pub fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// None for vectors too short to have a direction.
+pub fn normalize(a: Vec3) -> Option<Vec3> {
+    let len = dot(a, a).sqrt();
+    if len < 1e-6 {
+        None
+    } else {
+        Some(scale(a, 1.0 / len))
+    }
+}
+
+pub fn mul(a: &Mat3, b: &Mat3) -> Mat3 {
+    let mut m = [0.0; 9];
+    for r in 0..3 {
+        for c in 0..3 {
+            m[r * 3 + c] = (0..3).map(|k| a[r * 3 + k] * b[k * 3 + c]).sum();
+        }
+    }
+    m
+}
+
+pub fn transpose(m: &Mat3) -> Mat3 {
+    [
+        m[0], m[3], m[6],
+        m[1], m[4], m[7],
+        m[2], m[5], m[8],
+    ]
+}
+
+/// Nuitrack leaves the matrix zeroed when it has no orientation for a joint.
+pub fn is_rotation(m: &Mat3) -> bool {
+    let p = mul(m, &transpose(m));
+    p.iter().zip(IDENTITY.iter()).all(|(a, b)| (a - b).abs() < 0.05)
+}
+
+/// A rotation whose x axis is along `primary`
+/// and whose y axis is in the plane of both vectors.
+pub fn basis(primary: Vec3, secondary: Vec3) -> Option<Mat3> {
+    let x = normalize(primary)?;
+    let z = normalize(cross(x, secondary))?;
+    let y = cross(z, x);
+    Some([
+        x[0], y[0], z[0],
+        x[1], y[1], z[1],
+        x[2], y[2], z[2],
+    ])
+}
+
+/// The smallest rotation turning direction `from` onto direction `to`.
+pub fn arc(from: Vec3, to: Vec3) -> Mat3 {
+    let (from, to) = match (normalize(from), normalize(to)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return IDENTITY,
+    };
+    let v = cross(from, to);
+    let c = dot(from, to);
+    if c < -0.9999 {
+        // Half a turn about any axis at right angles to `from`
+        let other = if from[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+        let a = normalize(cross(from, other)).unwrap_or([0.0, 0.0, 1.0]);
+        return [
+            2.0 * a[0] * a[0] - 1.0, 2.0 * a[0] * a[1], 2.0 * a[0] * a[2],
+            2.0 * a[1] * a[0], 2.0 * a[1] * a[1] - 1.0, 2.0 * a[1] * a[2],
+            2.0 * a[2] * a[0], 2.0 * a[2] * a[1], 2.0 * a[2] * a[2] - 1.0,
+        ];
+    }
+    let k = 1.0 / (1.0 + c);
+    let vx: Mat3 = [
+        0.0, -v[2], v[1],
+        v[2], 0.0, -v[0],
+        -v[1], v[0], 0.0,
+    ];
+    let vx2 = mul(&vx, &vx);
+    let mut m = IDENTITY;
+    for i in 0..9 {
+        m[i] += vx[i] + vx2[i] * k;
+    }
+    m
+}
+
+/// The angles in degrees about z, x then y such that `m = Rz * Rx * Ry`.
+pub fn euler_zxy(m: &Mat3) -> Vec3 {
+    let x = m[7].max(-1.0).min(1.0).asin();
+    let (z, y) = if x.cos().abs() > 1e-6 {
+        ((-m[1]).atan2(m[4]), (-m[6]).atan2(m[8]))
+    } else {
+        // Gimbal lock, only z + y is known
+        (m[3].atan2(m[0]), 0.0)
+    };
+    [z.to_degrees(), x.to_degrees(), y.to_degrees()]
+}
+
+/// The unit quaternion `[x, y, z, w]` of a rotation.
+pub fn quaternion(m: &Mat3) -> [f32; 4] {
+    let trace = m[0] + m[4] + m[8];
+    let q = if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        [(m[7] - m[5]) / s, (m[2] - m[6]) / s, (m[3] - m[1]) / s, 0.25 * s]
+    } else if m[0] > m[4] && m[0] > m[8] {
+        let s = (1.0 + m[0] - m[4] - m[8]).sqrt() * 2.0;
+        [0.25 * s, (m[1] + m[3]) / s, (m[2] + m[6]) / s, (m[7] - m[5]) / s]
+    } else if m[4] > m[8] {
+        let s = (1.0 + m[4] - m[0] - m[8]).sqrt() * 2.0;
+        [(m[1] + m[3]) / s, 0.25 * s, (m[5] + m[7]) / s, (m[2] - m[6]) / s]
+    } else {
+        let s = (1.0 + m[8] - m[0] - m[4]).sqrt() * 2.0;
+        [(m[2] + m[6]) / s, (m[5] + m[7]) / s, 0.25 * s, (m[3] - m[1]) / s]
+    };
+    let len = q.iter().map(|v| v * v).sum::<f32>().sqrt();
+    [q[0] / len, q[1] / len, q[2] / len, q[3] / len]
+}
+
+pub fn mul_vec(m: &Mat3, v: Vec3) -> Vec3 {
+    [
+        m[0] * v[0] + m[1] * v[1] + m[2] * v[2],
+        m[3] * v[0] + m[4] * v[1] + m[5] * v[2],
+        m[6] * v[0] + m[7] * v[1] + m[8] * v[2],
+    ]
+}
@@ -0,0 +1,24 @@
+//! Writers that convert recordings and live data to other formats.
+
+mod bvh;
+mod c3d;
+mod gltf;
+mod hierarchy;
+mod images;
+mod math;
+mod ply;
+mod table;
+
+use joint_type::JointType;
+
+pub use self::bvh::{export_bvh, BvhWriter};
+pub use self::c3d::{export_c3d, write_c3d};
+pub use self::gltf::{export_gltf, GltfWriter};
+pub use self::images::{export_images, FileNaming, ImageFormat};
+pub use self::ply::{export_point_clouds, Intrinsics, PlyFormat, Point, PointCloud};
+pub use self::table::{export_table, SkeletonTable, TableFormat, TableLayout};
+
+/// Every joint type except `None`, in the nuitrack order.
+pub(crate) fn joint_types() -> Vec<JointType> {
+    (1..).map(JointType::from_u32).take_while(Option::is_some).filter_map(|t| t).collect()
+}
@@ -0,0 +1,253 @@
+//! Depth frames as PLY point clouds for MeshLab, CloudCompare and the like.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use byteorder::{LittleEndian, WriteBytesExt};
+use data::{DepthFrameFeed, RGBFrameFeed, UserFrameFeed};
+use edit::Frames;
+use errors::NuiError;
+use format::Stream;
+use super::images::FileNaming;
+
+/// The pinhole model of the depth camera, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Intrinsics {
+    pub fx: f32,
+    pub fy: f32,
+    pub cx: f32,
+    pub cy: f32,
+}
+
+impl Intrinsics {
+    /// Square pixels centred on the image, from the horizontal
+    /// field of view in radians such as `OutputMode::hfov`.
+    pub fn from_fov(rows: i32, cols: i32, hfov: f32) -> Self {
+        let f = cols as f32 / 2.0 / (hfov / 2.0).tan();
+        Intrinsics{ fx: f, fy: f, cx: cols as f32 / 2.0, cy: rows as f32 / 2.0 }
+    }
+}
+
+/// How a PLY file is encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    /// Little endian.
+    Binary,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    /// Millimetres with y up, like `Joint::real`.
+    pub position: [f32; 3],
+    /// RGB, black until the cloud is colorized.
+    pub color: [u8; 3],
+    /// The row and column of the depth pixel.
+    pub pixel: (i32, i32),
+}
+
+/// The points of a depth frame.
+///
+/// ```rust,no_run
+/// # use nuitrack_rs::{Intrinsics, PlyFormat, PointCloud};
+/// # fn cloud(depth: &nuitrack_rs::DepthFrame, color: &nuitrack_rs::RGBFrame) -> Result<(), nuitrack_rs::NuiError> {
+/// let intrinsics = Intrinsics::from_fov(depth.rows, depth.cols, 1.0);
+/// let mut cloud = PointCloud::from_depth(&depth.make_owned(), &intrinsics)?;
+/// cloud.colorize(&color.make_owned())?;
+/// cloud.save_ply("scene.ply", PlyFormat::Binary)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct PointCloud {
+    pub points: Vec<Point>,
+    /// The size of the depth frame.
+    rows: i32,
+    cols: i32,
+    colored: bool,
+}
+
+/// A frame to look pixels up in needs at least one of them.
+fn check_lookup(stream: &'static str, rows: i32, cols: i32, len: usize) -> Result<(), NuiError> {
+    if rows <= 0 || cols <= 0 || (rows * cols) as usize != len {
+        return Err(NuiError::BadFrame(stream));
+    }
+    Ok(())
+}
+
+/// The pixel of a `rows` x `cols` frame covering `pixel` of a `from_rows` x `from_cols` frame.
+fn scale_pixel(pixel: (i32, i32), from_rows: i32, from_cols: i32, rows: i32, cols: i32) -> usize {
+    let row = pixel.0 * rows / from_rows;
+    let col = pixel.1 * cols / from_cols;
+    (row * cols + col) as usize
+}
+
+impl PointCloud {
+    /// Projects every pixel with a depth into 3D. Pixels without depth are left out.
+    pub fn from_depth(depth: &DepthFrameFeed, intrinsics: &Intrinsics) -> Result<Self, NuiError> {
+        if (depth.rows * depth.cols) as usize != depth.data.len() {
+            return Err(NuiError::BadFrame("depth"));
+        }
+        let mut points = Vec::new();
+        for (i, &d) in depth.data.iter().enumerate() {
+            if d == 0 {
+                continue;
+            }
+            let pixel = (i as i32 / depth.cols, i as i32 % depth.cols);
+            let z = d as f32;
+            let x = (pixel.1 as f32 - intrinsics.cx) * z / intrinsics.fx;
+            let y = (intrinsics.cy - pixel.0 as f32) * z / intrinsics.fy;
+            points.push(Point{ position: [x, y, z], color: [0; 3], pixel });
+        }
+        Ok(PointCloud{ points, rows: depth.rows, cols: depth.cols, colored: false })
+    }
+
+    /// Colors each point from the matching pixel of a color frame.
+    /// The frames don't need the same resolution but an empty frame is an error.
+    pub fn colorize(&mut self, color: &RGBFrameFeed) -> Result<(), NuiError> {
+        check_lookup("color", color.rows, color.cols, color.data.len())?;
+        let (rows, cols) = (self.rows, self.cols);
+        for point in &mut self.points {
+            let c = color.data[scale_pixel(point.pixel, rows, cols, color.rows, color.cols)];
+            point.color = [c.red, c.green, c.blue];
+        }
+        self.colored = true;
+        Ok(())
+    }
+
+    /// Keeps the points on the user `id` in a user frame.
+    /// An empty user frame is an error.
+    pub fn keep_user(&mut self, users: &UserFrameFeed, id: i32) -> Result<(), NuiError> {
+        check_lookup("user", users.rows, users.cols, users.data.len())?;
+        let (rows, cols) = (self.rows, self.cols);
+        self.points.retain(|p| users.data[scale_pixel(p.pixel, rows, cols, users.rows, users.cols)] as i32 == id);
+        Ok(())
+    }
+
+    pub fn has_color(&self) -> bool {
+        self.colored
+    }
+
+    pub fn write_ply<W: Write>(&self, out: W, format: PlyFormat) -> Result<(), NuiError> {
+        let mut out = BufWriter::new(out);
+        writeln!(out, "ply")?;
+        match format {
+            PlyFormat::Ascii => writeln!(out, "format ascii 1.0")?,
+            PlyFormat::Binary => writeln!(out, "format binary_little_endian 1.0")?,
+        }
+        writeln!(out, "element vertex {}", self.points.len())?;
+        for axis in &["x", "y", "z"] {
+            writeln!(out, "property float {}", axis)?;
+        }
+        if self.colored {
+            for channel in &["red", "green", "blue"] {
+                writeln!(out, "property uchar {}", channel)?;
+            }
+        }
+        writeln!(out, "end_header")?;
+        for point in &self.points {
+            let [x, y, z] = point.position;
+            let [r, g, b] = point.color;
+            match format {
+                PlyFormat::Ascii if self.colored => writeln!(out, "{} {} {} {} {} {}", x, y, z, r, g, b)?,
+                PlyFormat::Ascii => writeln!(out, "{} {} {}", x, y, z)?,
+                PlyFormat::Binary => {
+                    for v in &point.position {
+                        out.write_f32::<LittleEndian>(*v)?;
+                    }
+                    if self.colored {
+                        out.write_all(&point.color)?;
+                    }
+                },
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn save_ply<P: AsRef<Path>>(&self, path: P, format: PlyFormat) -> Result<(), NuiError> {
+        self.write_ply(File::create(path)?, format)
+    }
+}
+
+/// Saves a point cloud for every depth frame of a recording into `dir`
+/// as `cloud-<name>.ply` files. Points are colorized when the recording
+/// has color, and only the points on `user` are kept if one is given.
+/// Frames without user data are then skipped.
+/// Returns the number of clouds written.
+pub fn export_point_clouds(recording: &Path, dir: &Path, intrinsics: &Intrinsics, format: PlyFormat, user: Option<i32>, naming: FileNaming) -> Result<usize, NuiError> {
+    let frames = Frames::open(recording)?;
+    if user.is_some() && !frames.header.streams.contains(&Stream::User) {
+        return Err(NuiError::BadExport("the recording has no user stream".to_string()));
+    }
+    fs::create_dir_all(dir)?;
+    let mut written = 0;
+    for (index, frame) in frames.enumerate() {
+        let frame = frame?;
+        let depth = match frame.depth {
+            Some(ref depth) => depth,
+            None => continue,
+        };
+        let mut cloud = PointCloud::from_depth(depth, intrinsics)?;
+        // A stream with no data yet has empty frames
+        if let Some(color) = frame.color.as_ref().filter(|c| !c.data.is_empty()) {
+            cloud.colorize(color)?;
+        }
+        if let Some(id) = user {
+            match frame.user.as_ref().filter(|u| !u.data.is_empty()) {
+                Some(users) => cloud.keep_user(users, id)?,
+                // There's no telling which points are the user's
+                None => continue,
+            }
+        }
+        let name = match naming {
+            FileNaming::Index => format!("cloud-{:06}.ply", index),
+            FileNaming::TimeStamp => format!("cloud-{}.ply", depth.stamp.time_stamp),
+        };
+        cloud.save_ply(dir.join(name), format)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use data::FrameStamp;
+    use nui::tdv::nuitrack::Vector3;
+    use super::*;
+
+    fn cloud() -> PointCloud {
+        let depth = DepthFrameFeed{ rows: 2, cols: 2, stamp: FrameStamp::default(), data: vec![1000, 0, 2000, 500] };
+        PointCloud::from_depth(&depth, &Intrinsics::from_fov(2, 2, 1.0)).unwrap()
+    }
+
+    fn users(rows: i32, cols: i32, data: Vec<u16>) -> UserFrameFeed {
+        let zero = Vector3{ x: 0.0, y: 0.0, z: 0.0 };
+        UserFrameFeed{ rows, cols, stamp: FrameStamp::default(), users: Vec::new(), data, floor: zero, floor_normal: zero }
+    }
+
+    #[test]
+    fn keep_user_points() {
+        let mut cloud = cloud();
+        cloud.keep_user(&users(1, 1, vec![1]), 1).unwrap();
+        assert_eq!(cloud.points.len(), 3);
+        cloud.keep_user(&users(2, 2, vec![1, 1, 2, 1]), 1).unwrap();
+        assert_eq!(cloud.points.iter().map(|p| p.pixel).collect::<Vec<_>>(), vec![(0, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn empty_frames_are_rejected() {
+        let mut cloud = cloud();
+        let color = RGBFrameFeed{ rows: 0, cols: 0, stamp: FrameStamp::default(), data: Vec::new() };
+        match cloud.colorize(&color) {
+            Err(NuiError::BadFrame("color")) => (),
+            other => panic!("expected BadFrame, got {:?}", other),
+        }
+        match cloud.keep_user(&users(0, 0, Vec::new()), 1) {
+            Err(NuiError::BadFrame("user")) => (),
+            other => panic!("expected BadFrame, got {:?}", other),
+        }
+        assert_eq!(cloud.points.len(), 3);
+        assert!(!cloud.has_color());
+    }
+}
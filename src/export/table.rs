@@ -0,0 +1,333 @@
+//! Joint data as CSV or JSON Lines tables for pandas, R and the like.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use serde::{Serialize, Serializer};
+use serde_json;
+use data::{SkeletonFeed, SkeletonDataFeed};
+use edit::Frames;
+use errors::NuiError;
+use export::joint_types;
+use joint_type::JointType;
+use nui::simple::SkeletonData;
+use nui::tdv::nuitrack::Joint;
+
+/// How rows are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableFormat {
+    /// Comma separated values with a header row.
+    Csv,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+/// How joints are spread over rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableLayout {
+    /// One row per frame, skeleton and joint.
+    Long,
+    /// One row per frame and skeleton with every joint in its own columns.
+    /// Joints that weren't tracked are left empty.
+    Wide,
+}
+
+/// Writes skeletons as a table, one frame at a time.
+///
+/// Every row has the frame number, time stamp and skeleton id.
+/// Each joint has its confidence, `real` and `proj` positions
+/// and the 9 values of its orientation matrix.
+///
+/// ```rust,no_run
+/// # use nuitrack_rs::{SkeletonTable, TableFormat, TableLayout};
+/// # use std::fs::File;
+/// # let mut nui = nuitrack_rs::init().expect("Couldn't initialize nuitrack");
+/// let file = File::create("joints.csv").expect("Couldn't create file");
+/// let mut table = SkeletonTable::new(file, TableFormat::Csv, TableLayout::Long);
+/// nui.skeleton_data(move |data| {
+///     table.write_data(&data).expect("Failed to write joints");
+/// }).expect("Failed to add callback");
+/// ```
+pub struct SkeletonTable<W: Write> {
+    out: W,
+    format: TableFormat,
+    layout: TableLayout,
+    header_written: bool,
+    /// The number of the next frame.
+    frame: u64,
+}
+
+const JOINT_COLUMNS: &'static [&'static str] = &[
+    "confidence",
+    "real_x", "real_y", "real_z",
+    "proj_x", "proj_y", "proj_z",
+    "orient_0", "orient_1", "orient_2", "orient_3", "orient_4",
+    "orient_5", "orient_6", "orient_7", "orient_8",
+];
+
+/// One joint's values in the order of `JOINT_COLUMNS`.
+#[derive(Serialize)]
+struct JointRow {
+    confidence: f32,
+    real: [f32; 3],
+    proj: [f32; 3],
+    orient: [f32; 9],
+}
+
+impl<'a> From<&'a Joint> for JointRow {
+    fn from(joint: &'a Joint) -> Self {
+        JointRow{
+            confidence: joint.confidence,
+            real: [joint.real.x, joint.real.y, joint.real.z],
+            proj: [joint.proj.x, joint.proj.y, joint.proj.z],
+            orient: joint.orient.matrix,
+        }
+    }
+}
+
+impl JointRow {
+    fn values(&self) -> Vec<f32> {
+        let mut values = vec![self.confidence];
+        values.extend_from_slice(&self.real);
+        values.extend_from_slice(&self.proj);
+        values.extend_from_slice(&self.orient);
+        values
+    }
+}
+
+#[derive(Serialize)]
+struct LongRow<'a> {
+    frame: u64,
+    time_stamp: u64,
+    skeleton_id: i32,
+    joint: &'static str,
+    #[serde(flatten)]
+    values: &'a JointRow,
+}
+
+#[derive(Serialize)]
+struct WideRow<'a> {
+    frame: u64,
+    time_stamp: u64,
+    skeleton_id: i32,
+    joints: JointMap<'a>,
+}
+
+/// Serializes as a map from joint name to values, keeping the joint order.
+struct JointMap<'a>(&'a [(JointType, JointRow)]);
+
+impl<'a> Serialize for JointMap<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|&(ref t, ref row)| (t.name(), row)))
+    }
+}
+
+impl<W: Write> SkeletonTable<W> {
+    pub fn new(out: W, format: TableFormat, layout: TableLayout) -> Self {
+        SkeletonTable{ out, format, layout, header_written: false, frame: 0 }
+    }
+
+    /// Writes a frame of live skeleton data.
+    pub fn write_data(&mut self, data: &SkeletonData) -> Result<(), NuiError> {
+        self.write_skeletons(data.time_stamp, &data.make_owned().skeletons)
+    }
+
+    /// Writes a frame of recorded skeleton data.
+    pub fn write_feed(&mut self, data: &SkeletonDataFeed) -> Result<(), NuiError> {
+        self.write_skeletons(data.time_stamp, &data.skeletons)
+    }
+
+    /// Writes a frame of skeletons captured at `time_stamp`.
+    pub fn write_skeletons(&mut self, time_stamp: u64, skeletons: &[SkeletonFeed]) -> Result<(), NuiError> {
+        if !self.header_written {
+            self.write_header()?;
+            self.header_written = true;
+        }
+        let frame = self.frame;
+        self.frame += 1;
+        for skeleton in skeletons {
+            let joints: Vec<(JointType, JointRow)> = skeleton.joints.iter()
+                .filter_map(|j| match JointType::from_u32(j.type_) {
+                    Some(JointType::None) | None => None,
+                    Some(t) => Some((t, JointRow::from(j))),
+                })
+                .collect();
+            match self.layout {
+                TableLayout::Long => for &(ref t, ref values) in &joints {
+                    let row = LongRow{ frame, time_stamp, skeleton_id: skeleton.id, joint: t.name(), values };
+                    self.write_long(&row)?;
+                },
+                TableLayout::Wide => {
+                    let row = WideRow{ frame, time_stamp, skeleton_id: skeleton.id, joints: JointMap(&joints) };
+                    self.write_wide(&row)?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), NuiError> {
+        self.out.flush()?;
+        Ok(())
+    }
+
+    /// Flushes and returns the writer.
+    pub fn into_inner(mut self) -> Result<W, NuiError> {
+        self.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_header(&mut self) -> Result<(), NuiError> {
+        if self.format != TableFormat::Csv {
+            return Ok(());
+        }
+        let mut columns = vec!["frame".to_string(), "time_stamp".to_string(), "skeleton_id".to_string()];
+        match self.layout {
+            TableLayout::Long => {
+                columns.push("joint".to_string());
+                columns.extend(JOINT_COLUMNS.iter().map(|c| c.to_string()));
+            },
+            TableLayout::Wide => for t in joint_types() {
+                columns.extend(JOINT_COLUMNS.iter().map(|c| format!("{}_{}", t.name(), c)));
+            },
+        }
+        writeln!(self.out, "{}", columns.join(","))?;
+        Ok(())
+    }
+
+    fn write_long(&mut self, row: &LongRow) -> Result<(), NuiError> {
+        match self.format {
+            TableFormat::Csv => {
+                write!(self.out, "{},{},{},{}", row.frame, row.time_stamp, row.skeleton_id, row.joint)?;
+                for v in row.values.values() {
+                    write!(self.out, ",{}", v)?;
+                }
+                writeln!(self.out)?;
+            },
+            TableFormat::JsonLines => self.write_json(row)?,
+        }
+        Ok(())
+    }
+
+    fn write_wide(&mut self, row: &WideRow) -> Result<(), NuiError> {
+        match self.format {
+            TableFormat::Csv => {
+                write!(self.out, "{},{},{}", row.frame, row.time_stamp, row.skeleton_id)?;
+                for t in joint_types() {
+                    match row.joints.0.iter().find(|&&(ref jt, _)| *jt == t) {
+                        Some(&(_, ref values)) => for v in values.values() {
+                            write!(self.out, ",{}", v)?;
+                        },
+                        None => for _ in JOINT_COLUMNS {
+                            write!(self.out, ",")?;
+                        },
+                    }
+                }
+                writeln!(self.out)?;
+            },
+            TableFormat::JsonLines => self.write_json(row)?,
+        }
+        Ok(())
+    }
+
+    fn write_json<T: Serialize>(&mut self, row: &T) -> Result<(), NuiError> {
+        serde_json::to_writer(&mut self.out, row)
+            .map_err(|e| NuiError::Io(e.into()))?;
+        writeln!(self.out)?;
+        Ok(())
+    }
+}
+
+/// Writes the skeletons of every frame of a recording to `output`.
+/// Returns the number of frames written.
+pub fn export_table(recording: &Path, output: &Path, format: TableFormat, layout: TableLayout) -> Result<usize, NuiError> {
+    let frames = Frames::open(recording)?;
+    let mut table = SkeletonTable::new(BufWriter::new(File::create(output)?), format, layout);
+    let mut written = 0;
+    for frame in frames {
+        if let Some(ref skeleton) = frame?.skeleton {
+            table.write_feed(skeleton)?;
+            written += 1;
+        }
+    }
+    table.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use nui::tdv::nuitrack::{Orientation, Vector3};
+    use super::*;
+
+    fn joint(type_: u32, v: f32) -> Joint {
+        Joint{
+            type_,
+            confidence: 0.5,
+            real: Vector3{ x: v, y: v + 1.0, z: v + 2.0 },
+            proj: Vector3{ x: 0.25, y: 0.5, z: 0.75 },
+            orient: Orientation{ matrix: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0] },
+        }
+    }
+
+    /// Skeleton 1 has a head and left shoulder, skeleton 2 a neck.
+    /// The `None` joint and an unknown type are left out.
+    fn skeletons() -> Vec<SkeletonFeed> {
+        vec![
+            SkeletonFeed{ id: 1, joints: vec![joint(0, 0.0), joint(1, 10.0), joint(6, 20.0)] },
+            SkeletonFeed{ id: 2, joints: vec![joint(2, 30.0), joint(99, 0.0)] },
+        ]
+    }
+
+    fn write(format: TableFormat, layout: TableLayout) -> Vec<String> {
+        let mut table = SkeletonTable::new(Vec::new(), format, layout);
+        table.write_skeletons(100, &skeletons()).unwrap();
+        table.write_skeletons(200, &skeletons()).unwrap();
+        let out = String::from_utf8(table.into_inner().unwrap()).unwrap();
+        out.lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn long_csv_has_a_row_per_joint() {
+        let lines = write(TableFormat::Csv, TableLayout::Long);
+        assert_eq!(lines.len(), 1 + 2 * 3);
+        assert!(lines[0].starts_with("frame,time_stamp,skeleton_id,joint,confidence,real_x,real_y,real_z,proj_x"));
+        assert_eq!(lines[1], "0,100,1,head,0.5,10,11,12,0.25,0.5,0.75,1,0,0,0,1,0,0,0,1");
+        assert!(lines[2].starts_with("0,100,1,left_shoulder,0.5,20,"));
+        assert!(lines[3].starts_with("0,100,2,neck,"));
+        assert!(lines[4].starts_with("1,200,1,head,"));
+        assert!(lines.iter().all(|l| l.split(',').count() == 4 + JOINT_COLUMNS.len()));
+    }
+
+    #[test]
+    fn wide_csv_leaves_missing_joints_empty() {
+        let lines = write(TableFormat::Csv, TableLayout::Wide);
+        assert_eq!(lines.len(), 1 + 2 * 2);
+        let columns = 3 + joint_types().len() * JOINT_COLUMNS.len();
+        assert!(lines.iter().all(|l| l.split(',').count() == columns));
+        assert!(lines[0].starts_with("frame,time_stamp,skeleton_id,head_confidence,"));
+        assert!(lines[1].starts_with("0,100,1,0.5,10,11,12,"));
+        // Skeleton 2 has no head
+        assert!(lines[2].starts_with(&format!("0,100,2{}", ",".repeat(JOINT_COLUMNS.len()))));
+    }
+
+    #[test]
+    fn json_lines() {
+        let lines = write(TableFormat::JsonLines, TableLayout::Long);
+        assert_eq!(lines.len(), 2 * 3);
+        let row: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(row["frame"], 0);
+        assert_eq!(row["time_stamp"], 100);
+        assert_eq!(row["joint"], "head");
+        assert_eq!(row["real"][2], 12.0);
+        assert_eq!(row["orient"].as_array().unwrap().len(), 9);
+
+        let lines = write(TableFormat::JsonLines, TableLayout::Wide);
+        assert_eq!(lines.len(), 2 * 2);
+        let row: Value = serde_json::from_str(&lines[2]).unwrap();
+        assert_eq!((row["frame"].as_u64(), row["skeleton_id"].as_i64()), (Some(1), Some(1)));
+        assert_eq!(row["joints"]["left_shoulder"]["real"][0], 20.0);
+        assert!(row["joints"].get("none").is_none());
+        assert!(row["joints"].get("neck").is_none());
+    }
+}
@@ -0,0 +1,778 @@
+//! The on disk layout of a recording.
+//!
+//! A recording starts with `MAGIC`, the format version and a length
+//! prefixed `Header`. The frames follow, each one a self delimiting chunk
+//! holding a snappy compressed, bincode encoded `TimePoint`.
+//! Depth and color are first encoded with the codecs chosen in the header.
+//! The file ends with an index of where each frame starts so a player
+//! can seek without decoding the whole file. The index also stores the
+//! size of each stream and the number of skeletons in every frame
+//! so a recording can be summarised from the index alone.
+//! All integers are little endian and every CRC is a CRC-32.
+//!
+//! ```text
+//! header:  MAGIC | version: u32 | len: u32 | crc: u32 | data: [u8; len]
+//! frame:   FRAME_MAGIC | len: u32 | time_stamp: u64 | crc: u32 | data: [u8; len]
+//! index:   (offset: u64 | time_stamp: u64 | sizes: [u32; 4] | skeletons: u32) * frame_count
+//! footer:  index_offset: u64 | frame_count: u64 | crc: u32 | INDEX_MAGIC
+//! ```
+//!
+//! A frame's CRC covers its length, time stamp and data.
+//! The footer's CRC covers the index.
+//! If a recording is cut short, the index is rebuilt from the frames
+//! whose CRC matches, skipping over anything else to the next `FRAME_MAGIC`.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+use bincode;
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use crc32fast::{self, Hasher};
+use codec::Codecs;
+use data::{FrameStamp, SkeletonDataFeed, DepthFrameFeed, RGBFrameFeed, UserFrameFeed};
+use errors::NuiError;
+use legacy;
+use recorder::TimePoint;
+use serde::Serialize;
+use snap;
+
+/// The first bytes of every recording.
+pub const MAGIC: &'static [u8; 8] = b"NUITRACK";
+
+/// Bump this whenever the layout of the header or the frames changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The last bytes of a recording with an index.
+pub const INDEX_MAGIC: &'static [u8; 8] = b"NUIINDEX";
+
+/// The first bytes of every frame.
+pub const FRAME_MAGIC: &'static [u8; 4] = b"NUIF";
+
+const FRAME_PREFIX_LEN: u64 = 4 + 4 + 8 + 4;
+/// The size of one frame's entry in the index.
+pub const INDEX_ENTRY_LEN: u64 = 8 + 8 + 4 * 4 + 4;
+/// The largest image side that is accepted when reading.
+const MAX_SIDE: i32 = 1 << 14;
+/// The largest compressed frame that is accepted when reading.
+const MAX_FRAME_LEN: u32 = 1 << 28;
+const FOOTER_LEN: u64 = 8 + 8 + 4 + 8;
+/// How much is read at a time when looking for the next frame.
+const SCAN_BLOCK: usize = 64 * 1024;
+
+/// A duration in microseconds, the unit of every time stamp.
+pub fn micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + duration.subsec_micros() as u64
+}
+
+/// A data stream that is stored in a recording.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    Skeleton,
+    Depth,
+    Color,
+    User,
+}
+
+impl Stream {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stream::Skeleton => "skeleton",
+            Stream::Depth => "depth",
+            Stream::Color => "color",
+            Stream::User => "user",
+        }
+    }
+
+    /// The stream with this `name`.
+    pub fn from_name(name: &str) -> Option<Stream> {
+        match name {
+            "skeleton" => Some(Stream::Skeleton),
+            "depth" => Some(Stream::Depth),
+            "color" => Some(Stream::Color),
+            "user" => Some(Stream::User),
+            _ => None,
+        }
+    }
+}
+
+/// Metadata written once at the start of a recording.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Header {
+    /// Version of nuitrack-rs that wrote the recording.
+    pub crate_version: String,
+    /// The streams stored in each frame.
+    pub streams: Vec<Stream>,
+    pub rows: i32,
+    pub cols: i32,
+    /// Seconds since the unix epoch.
+    pub created: u64,
+    /// How each stream is encoded.
+    pub codecs: Codecs,
+    /// When the recorder that wrote this file was created.
+    /// Files rotated from one recorder share it.
+    pub session: u64,
+    /// The position of this file in its session, starting at zero.
+    pub part: u32,
+}
+
+impl Header {
+    pub fn new(streams: Vec<Stream>, rows: i32, cols: i32, created: u64, codecs: Codecs, session: u64, part: u32) -> Self {
+        Header {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            streams,
+            rows,
+            cols,
+            created,
+            codecs,
+            session,
+            part,
+        }
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<(), NuiError> {
+        let bytes = bincode::serialize(self)?;
+        w.write_all(MAGIC)?;
+        w.write_u32::<LittleEndian>(FORMAT_VERSION)?;
+        w.write_u32::<LittleEndian>(bytes.len() as u32)?;
+        w.write_u32::<LittleEndian>(crc32fast::hash(&bytes))?;
+        w.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Reads the header and checks the file is a recording
+    /// this version of the crate understands.
+    pub fn read<R: Read>(r: &mut R) -> Result<Self, NuiError> {
+        let mut magic = [0u8; 8];
+        if r.read_exact(&mut magic).is_err() {
+            return Err(NuiError::NotARecording);
+        }
+        if legacy::STREAM_IDENTIFIER.starts_with(&magic) {
+            return Err(NuiError::LegacyRecording);
+        }
+        if &magic != MAGIC {
+            return Err(NuiError::NotARecording);
+        }
+        let cut_short = |e: io::Error| if e.kind() == io::ErrorKind::UnexpectedEof {
+            NuiError::Corrupt("header is cut short".to_string())
+        } else {
+            e.into()
+        };
+        let version = r.read_u32::<LittleEndian>().map_err(cut_short)?;
+        if version != FORMAT_VERSION {
+            return Err(NuiError::UnsupportedVersion(version));
+        }
+        let len = r.read_u32::<LittleEndian>().map_err(cut_short)?;
+        let crc = r.read_u32::<LittleEndian>().map_err(cut_short)?;
+        if len > MAX_FRAME_LEN {
+            return Err(NuiError::Corrupt("header is too long".to_string()));
+        }
+        let mut bytes = vec![0u8; len as usize];
+        r.read_exact(&mut bytes).map_err(cut_short)?;
+        if crc32fast::hash(&bytes) != crc {
+            return Err(NuiError::Corrupt("header checksum doesn't match".to_string()));
+        }
+        Ok(bincode::deserialize(&bytes)?)
+    }
+}
+
+/// Where a frame starts in the file and when it was captured.
+#[derive(Clone, Copy, Debug)]
+pub struct IndexEntry {
+    pub offset: u64,
+    pub time_stamp: u64,
+    pub stats: FrameStats,
+}
+
+/// What is in a frame, as stored in the index.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// The encoded bytes of each stream before the frame is compressed,
+    /// in the order skeleton, depth, color, user.
+    pub sizes: [u32; 4],
+    /// The number of skeletons tracked in the frame.
+    pub skeletons: u32,
+}
+
+impl FrameStats {
+    fn new(
+        skeleton: &Option<SkeletonDataFeed>,
+        depth: &Option<EncodedImage>,
+        color: &Option<EncodedImage>,
+        user: &Option<UserFrameFeed>) -> Result<Self, NuiError>
+    {
+        Ok(FrameStats{
+            sizes: [stream_size(skeleton)?, stream_size(depth)?, stream_size(color)?, stream_size(user)?],
+            skeletons: skeleton.as_ref().map_or(0, |s| s.skeletons.len() as u32),
+        })
+    }
+}
+
+/// Zero for a stream that isn't in the frame.
+fn stream_size<T: Serialize>(stream: &Option<T>) -> Result<u32, NuiError> {
+    match *stream {
+        Some(ref s) => Ok(bincode::serialized_size(s)?.min(u32::max_value() as u64) as u32),
+        None => Ok(0),
+    }
+}
+
+/// A `TimePoint` as it is stored, with the images encoded.
+/// Serializes the same as `StoredFrame`.
+#[derive(Serialize)]
+struct StoredFrameRef<'a> {
+    skeleton: &'a Option<SkeletonDataFeed>,
+    depth: Option<EncodedImage>,
+    color: Option<EncodedImage>,
+    user: &'a Option<UserFrameFeed>,
+}
+
+#[derive(Deserialize)]
+struct StoredFrame {
+    skeleton: Option<SkeletonDataFeed>,
+    depth: Option<EncodedImage>,
+    color: Option<EncodedImage>,
+    user: Option<UserFrameFeed>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncodedImage {
+    rows: i32,
+    cols: i32,
+    stamp: FrameStamp,
+    data: Vec<u8>,
+}
+
+/// Returns the number of bytes written and the frame's index stats.
+pub fn write_frame<W: Write>(w: &mut W, frame: &TimePoint, codecs: &Codecs) -> Result<(u64, FrameStats), NuiError> {
+    let depth = frame.depth.as_ref().map(|d| EncodedImage{
+        rows: d.rows,
+        cols: d.cols,
+        stamp: d.stamp,
+        data: codecs.depth.encode(&d.data),
+    });
+    let color = match frame.color {
+        Some(ref c) => Some(EncodedImage{
+            rows: c.rows,
+            cols: c.cols,
+            stamp: c.stamp,
+            data: codecs.color.encode(&c.data, c.rows, c.cols)?,
+        }),
+        None => None,
+    };
+    let stored = StoredFrameRef{
+        skeleton: &frame.skeleton,
+        depth,
+        color,
+        user: &frame.user,
+    };
+    let stats = FrameStats::new(stored.skeleton, &stored.depth, &stored.color, stored.user)?;
+    let bytes = bincode::serialize(&stored)?;
+    let bytes = snap::Encoder::new().compress_vec(&bytes)?;
+    Ok((write_chunk(w, frame.time_stamp(), &bytes)?, stats))
+}
+
+/// Returns `None` when there are no more frames.
+pub fn read_frame<R: Read>(r: &mut R, codecs: &Codecs) -> Result<Option<TimePoint>, NuiError> {
+    Ok(read_sized_frame(r, codecs)?.map(|(frame, _)| frame))
+}
+
+/// Like `read_frame` but also returns the number of bytes the frame took up.
+pub fn read_sized_frame<R: Read>(r: &mut R, codecs: &Codecs) -> Result<Option<(TimePoint, u64)>, NuiError> {
+    let bytes = match read_chunk(r)? {
+        Some(chunk) => chunk.data,
+        None => return Ok(None),
+    };
+    let len = FRAME_PREFIX_LEN + bytes.len() as u64;
+    let bytes = snap::Decoder::new().decompress_vec(&bytes)?;
+    let StoredFrame{ skeleton, depth, color, user } = bincode::deserialize(&bytes)?;
+    let depth = match depth {
+        Some(d) => {
+            let len = pixels(Stream::Depth, d.rows, d.cols)?;
+            let data = codecs.depth.decode(&d.data, len)?;
+            Some(DepthFrameFeed{ rows: d.rows, cols: d.cols, stamp: d.stamp, data })
+        },
+        None => None,
+    };
+    let color = match color {
+        Some(c) => {
+            pixels(Stream::Color, c.rows, c.cols)?;
+            let data = codecs.color.decode(&c.data, c.rows, c.cols)?;
+            Some(RGBFrameFeed{ rows: c.rows, cols: c.cols, stamp: c.stamp, data })
+        },
+        None => None,
+    };
+    let frame = TimePoint{ skeleton, depth, color, user };
+    check_frame(&frame)?;
+    Ok(Some((frame, len)))
+}
+
+/// A frame's compressed data as it is stored.
+struct Chunk {
+    time_stamp: u64,
+    data: Vec<u8>,
+}
+
+/// Returns the number of bytes written.
+fn write_chunk<W: Write>(w: &mut W, time_stamp: u64, data: &[u8]) -> io::Result<u64> {
+    let mut prefix = [0u8; 12];
+    LittleEndian::write_u32(&mut prefix[..4], data.len() as u32);
+    LittleEndian::write_u64(&mut prefix[4..], time_stamp);
+    let mut hasher = Hasher::new();
+    hasher.update(&prefix);
+    hasher.update(data);
+    w.write_all(FRAME_MAGIC)?;
+    w.write_all(&prefix)?;
+    w.write_u32::<LittleEndian>(hasher.finalize())?;
+    w.write_all(data)?;
+    Ok(FRAME_PREFIX_LEN + data.len() as u64)
+}
+
+/// Reads and checks the chunk at the reader's position.
+/// Returns `None` at the end of the file.
+fn read_chunk<R: Read>(r: &mut R) -> Result<Option<Chunk>, NuiError> {
+    let mut magic = [0u8; 4];
+    match r.read_exact(&mut magic) {
+        Ok(()) => (),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    if &magic != FRAME_MAGIC {
+        return Err(NuiError::Corrupt("frame doesn't start with the frame magic".to_string()));
+    }
+    // The file ending inside a frame means it was cut short or its length is damaged
+    let cut_short = |e: io::Error| if e.kind() == io::ErrorKind::UnexpectedEof {
+        NuiError::Corrupt("frame is cut short".to_string())
+    } else {
+        e.into()
+    };
+    let mut prefix = [0u8; 12];
+    r.read_exact(&mut prefix).map_err(cut_short)?;
+    let len = LittleEndian::read_u32(&prefix[..4]);
+    let time_stamp = LittleEndian::read_u64(&prefix[4..]);
+    let crc = r.read_u32::<LittleEndian>().map_err(cut_short)?;
+    if len > MAX_FRAME_LEN {
+        return Err(NuiError::Corrupt("frame is too long".to_string()));
+    }
+    let mut data = vec![0u8; len as usize];
+    r.read_exact(&mut data).map_err(cut_short)?;
+    let mut hasher = Hasher::new();
+    hasher.update(&prefix);
+    hasher.update(&data);
+    if hasher.finalize() != crc {
+        return Err(NuiError::Corrupt("frame checksum doesn't match".to_string()));
+    }
+    Ok(Some(Chunk{ time_stamp, data }))
+}
+
+/// Playback hands out pointers into the frame data
+/// so the lengths must match the resolution.
+pub fn check_frame(frame: &TimePoint) -> Result<(), NuiError> {
+    let check = |stream: Stream, rows: i32, cols: i32, len: usize| {
+        if pixels(stream, rows, cols)? != len {
+            Err(NuiError::Corrupt(format!("{} frame is {}x{} but has {} values", stream.name(), rows, cols, len)))
+        } else {
+            Ok(())
+        }
+    };
+    if let Some(ref d) = frame.depth {
+        check(Stream::Depth, d.rows, d.cols, d.data.len())?;
+    }
+    if let Some(ref c) = frame.color {
+        check(Stream::Color, c.rows, c.cols, c.data.len())?;
+    }
+    if let Some(ref u) = frame.user {
+        check(Stream::User, u.rows, u.cols, u.data.len())?;
+    }
+    Ok(())
+}
+
+/// The number of pixels in an image, checking the size is sane
+/// so a corrupt frame can't cause a huge allocation.
+fn pixels(stream: Stream, rows: i32, cols: i32) -> Result<usize, NuiError> {
+    if rows < 0 || cols < 0 || rows > MAX_SIDE || cols > MAX_SIDE {
+        return Err(NuiError::Corrupt(format!("{} frame has an invalid size {}x{}", stream.name(), rows, cols)));
+    }
+    Ok(rows as usize * cols as usize)
+}
+
+/// Writes the index and footer. `index_offset` is where the index starts.
+pub fn write_index<W: Write>(w: &mut W, index: &[IndexEntry], index_offset: u64) -> io::Result<()> {
+    let bytes = index_bytes(index);
+    w.write_all(&bytes)?;
+    w.write_u64::<LittleEndian>(index_offset)?;
+    w.write_u64::<LittleEndian>(index.len() as u64)?;
+    w.write_u32::<LittleEndian>(crc32fast::hash(&bytes))?;
+    w.write_all(INDEX_MAGIC)
+}
+
+fn index_bytes(index: &[IndexEntry]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(index.len() * INDEX_ENTRY_LEN as usize);
+    for entry in index {
+        bytes.write_u64::<LittleEndian>(entry.offset).expect("writing to a vec can't fail");
+        bytes.write_u64::<LittleEndian>(entry.time_stamp).expect("writing to a vec can't fail");
+        for size in &entry.stats.sizes {
+            bytes.write_u32::<LittleEndian>(*size).expect("writing to a vec can't fail");
+        }
+        bytes.write_u32::<LittleEndian>(entry.stats.skeletons).expect("writing to a vec can't fail");
+    }
+    bytes
+}
+
+/// Reads the index from the footer.
+///
+/// If the footer is missing or damaged, because the recording was never
+/// flushed or the writer died, the index is rebuilt from the frames.
+pub fn read_index<R: Read + Seek>(r: &mut R, data_start: u64) -> io::Result<Vec<IndexEntry>> {
+    let file_len = r.seek(SeekFrom::End(0))?;
+    if let Some(index) = read_footer(r, data_start, file_len)? {
+        return Ok(index);
+    }
+    scan_frames(r, data_start, file_len)
+}
+
+/// Whether the recording ends with an undamaged index.
+pub fn has_index<R: Read + Seek>(r: &mut R, data_start: u64) -> io::Result<bool> {
+    let file_len = r.seek(SeekFrom::End(0))?;
+    Ok(read_footer(r, data_start, file_len)?.is_some())
+}
+
+/// Finds every frame with a valid CRC.
+/// After a damaged frame it carries on from the next `FRAME_MAGIC`.
+/// Each frame is decompressed to work out its stats.
+fn scan_frames<R: Read + Seek>(r: &mut R, data_start: u64, file_len: u64) -> io::Result<Vec<IndexEntry>> {
+    let mut index = Vec::new();
+    let mut offset = data_start;
+    while offset + FRAME_PREFIX_LEN <= file_len {
+        r.seek(SeekFrom::Start(offset))?;
+        let damaged = match read_chunk(&mut (&mut *r).take(file_len - offset)) {
+            Ok(Some(chunk)) => match chunk_stats(&chunk) {
+                Ok(stats) => {
+                    index.push(IndexEntry{ offset, time_stamp: chunk.time_stamp, stats });
+                    offset += FRAME_PREFIX_LEN + chunk.data.len() as u64;
+                    false
+                },
+                // The CRC matched but the frame can't be read
+                Err(_) => true,
+            },
+            Ok(None) => break,
+            Err(NuiError::Io(e)) => return Err(e),
+            // Including a frame cut short by the end of the file
+            Err(_) => true,
+        };
+        if damaged {
+            match find_frame_magic(r, offset + 1, file_len)? {
+                Some(next) => offset = next,
+                None => break,
+            }
+        }
+    }
+    Ok(index)
+}
+
+fn chunk_stats(chunk: &Chunk) -> Result<FrameStats, NuiError> {
+    let bytes = snap::Decoder::new().decompress_vec(&chunk.data)?;
+    let StoredFrame{ skeleton, depth, color, user } = bincode::deserialize(&bytes)?;
+    FrameStats::new(&skeleton, &depth, &color, &user)
+}
+
+/// The offset of the next `FRAME_MAGIC` at or after `from`.
+fn find_frame_magic<R: Read + Seek>(r: &mut R, from: u64, file_len: u64) -> io::Result<Option<u64>> {
+    let overlap = FRAME_MAGIC.len() - 1;
+    let mut block = vec![0u8; SCAN_BLOCK];
+    let mut start = from;
+    while start + (FRAME_MAGIC.len() as u64) <= file_len {
+        r.seek(SeekFrom::Start(start))?;
+        let len = (file_len - start).min(SCAN_BLOCK as u64) as usize;
+        r.read_exact(&mut block[..len])?;
+        if let Some(i) = block[..len].windows(FRAME_MAGIC.len()).position(|w| w == FRAME_MAGIC) {
+            return Ok(Some(start + i as u64));
+        }
+        // Keep the end of the block in case the magic is split across two
+        start += (len - overlap) as u64;
+    }
+    Ok(None)
+}
+
+fn read_footer<R: Read + Seek>(r: &mut R, data_start: u64, file_len: u64) -> io::Result<Option<Vec<IndexEntry>>> {
+    if file_len < data_start + FOOTER_LEN {
+        return Ok(None);
+    }
+    r.seek(SeekFrom::Start(file_len - FOOTER_LEN))?;
+    let index_offset = r.read_u64::<LittleEndian>()?;
+    let frame_count = r.read_u64::<LittleEndian>()?;
+    let crc = r.read_u32::<LittleEndian>()?;
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != INDEX_MAGIC
+        || index_offset < data_start
+        || index_offset.checked_add(frame_count.saturating_mul(INDEX_ENTRY_LEN)) != Some(file_len - FOOTER_LEN) {
+        return Ok(None);
+    }
+    r.seek(SeekFrom::Start(index_offset))?;
+    let mut bytes = vec![0u8; (frame_count * INDEX_ENTRY_LEN) as usize];
+    r.read_exact(&mut bytes)?;
+    if crc32fast::hash(&bytes) != crc {
+        return Ok(None);
+    }
+    let index = bytes.chunks(INDEX_ENTRY_LEN as usize)
+        .map(|e| {
+            let mut sizes = [0u32; 4];
+            LittleEndian::read_u32_into(&e[16..32], &mut sizes);
+            IndexEntry{
+                offset: LittleEndian::read_u64(&e[..8]),
+                time_stamp: LittleEndian::read_u64(&e[8..16]),
+                stats: FrameStats{ sizes, skeletons: LittleEndian::read_u32(&e[32..]) },
+            }
+        })
+        .collect();
+    Ok(Some(index))
+}
+
+/// Rewrites a damaged recording so it can be played and seeked.
+///
+/// Keeps every frame whose CRC matches, drops everything else
+/// and writes a new index. Returns the number of frames kept.
+pub fn recover(path: &Path) -> Result<usize, NuiError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let header = Header::read(&mut reader)?;
+    let data_start = reader.seek(SeekFrom::Current(0))?;
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    let frames = scan_frames(&mut reader, data_start, file_len)?;
+    let tmp = path.with_extension("recovering");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    header.write(&mut writer)?;
+    let mut offset = writer.seek(SeekFrom::Current(0))?;
+    let mut index = Vec::with_capacity(frames.len());
+    for entry in frames {
+        reader.seek(SeekFrom::Start(entry.offset))?;
+        let chunk = read_chunk(&mut reader)?
+            .ok_or_else(|| NuiError::Corrupt("frame disappeared while recovering".to_string()))?;
+        index.push(IndexEntry{ offset, time_stamp: chunk.time_stamp, stats: entry.stats });
+        offset += write_chunk(&mut writer, chunk.time_stamp, &chunk.data)?;
+    }
+    write_index(&mut writer, &index, offset)?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(index.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Cursor;
+    use std::process;
+    use super::*;
+
+    fn frame(time_stamp: u64) -> TimePoint {
+        let stamp = FrameStamp{ id: time_stamp / 1000, time_stamp };
+        TimePoint{
+            skeleton: Some(SkeletonDataFeed{ time_stamp, skeletons: Vec::new() }),
+            depth: Some(DepthFrameFeed{ rows: 2, cols: 3, stamp, data: vec![0, 800, 801, 0, 0, 4000] }),
+            ..TimePoint::default()
+        }
+    }
+
+    fn header() -> Header {
+        Header::new(vec![Stream::Skeleton, Stream::Depth], 2, 3, 1234, Codecs::default(), 1234, 0)
+    }
+
+    /// A header, `count` frames 33ms apart and the index.
+    fn recording(count: u64) -> (Vec<u8>, Vec<IndexEntry>) {
+        let mut bytes = Vec::new();
+        header().write(&mut bytes).unwrap();
+        let mut index = Vec::new();
+        for i in 0..count {
+            let offset = bytes.len() as u64;
+            let (_, stats) = write_frame(&mut bytes, &frame(i * 33_000), &Codecs::default()).unwrap();
+            index.push(IndexEntry{ offset, time_stamp: i * 33_000, stats });
+        }
+        let index_offset = bytes.len() as u64;
+        write_index(&mut bytes, &index, index_offset).unwrap();
+        (bytes, index)
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let mut bytes = Vec::new();
+        header().write(&mut bytes).unwrap();
+        assert_eq!(&bytes[..8], MAGIC);
+        assert_eq!(LittleEndian::read_u32(&bytes[8..12]), FORMAT_VERSION);
+        let read = Header::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(read.streams, vec![Stream::Skeleton, Stream::Depth]);
+        assert_eq!((read.rows, read.cols, read.created), (2, 3, 1234));
+        assert_eq!(read.codecs, Codecs::default());
+    }
+
+    #[test]
+    fn header_rejects_other_files() {
+        match Header::read(&mut Cursor::new(b"not a recording at all")) {
+            Err(NuiError::NotARecording) => (),
+            other => panic!("expected NotARecording, got {:?}", other.map(|_| ())),
+        }
+        let mut bytes = Vec::new();
+        header().write(&mut bytes).unwrap();
+        LittleEndian::write_u32(&mut bytes[8..12], FORMAT_VERSION + 1);
+        match Header::read(&mut Cursor::new(&bytes)) {
+            Err(NuiError::UnsupportedVersion(v)) => assert_eq!(v, FORMAT_VERSION + 1),
+            other => panic!("expected UnsupportedVersion, got {:?}", other.map(|_| ())),
+        }
+        match Header::read(&mut Cursor::new(&legacy::STREAM_IDENTIFIER[..])) {
+            Err(NuiError::LegacyRecording) => (),
+            other => panic!("expected LegacyRecording, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn header_crc_mismatch() {
+        let mut bytes = Vec::new();
+        header().write(&mut bytes).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        match Header::read(&mut Cursor::new(&bytes)) {
+            Err(NuiError::Corrupt(_)) => (),
+            other => panic!("expected Corrupt, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let mut bytes = Vec::new();
+        let (len, stats) = write_frame(&mut bytes, &frame(66_000), &Codecs::default()).unwrap();
+        assert_eq!(len, bytes.len() as u64);
+        assert_eq!(&bytes[..4], FRAME_MAGIC);
+        assert!(stats.sizes[1] > 0);
+        assert_eq!(stats.sizes[2], 0);
+        let mut r = Cursor::new(&bytes);
+        let read = read_frame(&mut r, &Codecs::default()).unwrap().unwrap();
+        assert_eq!(read.time_stamp(), 66_000);
+        let depth = read.depth.unwrap();
+        assert_eq!(depth.stamp, FrameStamp{ id: 66, time_stamp: 66_000 });
+        assert_eq!(depth.data, vec![0, 800, 801, 0, 0, 4000]);
+        assert_eq!(read.skeleton.unwrap().time_stamp, 66_000);
+        assert!(read.color.is_none());
+        // The end of the file is not an error
+        assert!(read_frame(&mut r, &Codecs::default()).unwrap().is_none());
+    }
+
+    #[test]
+    fn frame_crc_mismatch() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, &frame(0), &Codecs::default()).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        match read_frame(&mut Cursor::new(&bytes), &Codecs::default()) {
+            Err(NuiError::Corrupt(_)) => (),
+            other => panic!("expected Corrupt, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn frame_length_past_the_end() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, &frame(0), &Codecs::default()).unwrap();
+        // A length that is allowed but runs off the end of the file
+        let len = bytes.len() as u32;
+        LittleEndian::write_u32(&mut bytes[4..8], len);
+        match read_frame(&mut Cursor::new(&bytes), &Codecs::default()) {
+            Err(NuiError::Corrupt(_)) => (),
+            other => panic!("expected Corrupt, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let (bytes, written) = recording(5);
+        let mut r = Cursor::new(&bytes);
+        Header::read(&mut r).unwrap();
+        let data_start = r.position();
+        assert!(has_index(&mut r, data_start).unwrap());
+        let index = read_index(&mut r, data_start).unwrap();
+        assert_eq!(index.len(), 5);
+        for (read, written) in index.iter().zip(&written) {
+            assert_eq!(read.offset, written.offset);
+            assert_eq!(read.time_stamp, written.time_stamp);
+            assert_eq!(read.stats, written.stats);
+        }
+    }
+
+    #[test]
+    fn index_crc_mismatch_rebuilds_from_frames() {
+        let (mut bytes, written) = recording(5);
+        // The last entry's skeleton count, covered by the footer's CRC
+        let entry = bytes.len() - FOOTER_LEN as usize - 1;
+        bytes[entry] ^= 0xff;
+        let mut r = Cursor::new(&bytes);
+        Header::read(&mut r).unwrap();
+        let data_start = r.position();
+        assert!(!has_index(&mut r, data_start).unwrap());
+        let index = read_index(&mut r, data_start).unwrap();
+        let offsets: Vec<u64> = index.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, written.iter().map(|e| e.offset).collect::<Vec<_>>());
+        assert_eq!(index[4].stats, written[4].stats);
+    }
+
+    /// Writes `bytes` to a new file, recovers it and returns
+    /// the time stamps of the frames kept, in milliseconds.
+    fn recovered(name: &str, bytes: &[u8]) -> Vec<u64> {
+        let path = env::temp_dir().join(format!("nuitrack-rs-recover-{}-{}.snap", name, process::id()));
+        fs::write(&path, bytes).unwrap();
+        let kept = recover(&path).unwrap();
+        let mut r = BufReader::new(File::open(&path).unwrap());
+        Header::read(&mut r).unwrap();
+        let data_start = r.seek(SeekFrom::Current(0)).unwrap();
+        assert!(has_index(&mut r, data_start).unwrap());
+        let index = read_index(&mut r, data_start).unwrap();
+        assert_eq!(index.len(), kept);
+        let stamps = index.iter()
+            .map(|e| {
+                r.seek(SeekFrom::Start(e.offset)).unwrap();
+                let frame = read_frame(&mut r, &Codecs::default()).unwrap().unwrap();
+                assert_eq!(frame.time_stamp(), e.time_stamp);
+                e.time_stamp / 1000
+            })
+            .collect();
+        fs::remove_file(&path).ok();
+        stamps
+    }
+
+    #[test]
+    fn recover_without_index() {
+        let (bytes, index) = recording(4);
+        let index_offset = index[3].offset as usize + FRAME_PREFIX_LEN as usize
+            + LittleEndian::read_u32(&bytes[index[3].offset as usize + 4..]) as usize;
+        assert_eq!(recovered("no-index", &bytes[..index_offset]), vec![0, 33, 66, 99]);
+    }
+
+    #[test]
+    fn recover_cut_mid_frame() {
+        let (bytes, index) = recording(4);
+        // Half way through the third frame
+        let cut = (index[2].offset + index[3].offset) as usize / 2;
+        assert_eq!(recovered("cut", &bytes[..cut]), vec![0, 33]);
+        // Inside the third frame's prefix
+        assert_eq!(recovered("cut-prefix", &bytes[..index[2].offset as usize + 6]), vec![0, 33]);
+    }
+
+    #[test]
+    fn recover_skips_damaged_frames() {
+        let (mut bytes, index) = recording(5);
+        // Data of the second frame and the length of the fourth
+        bytes[index[1].offset as usize + FRAME_PREFIX_LEN as usize] ^= 0xff;
+        bytes[index[3].offset as usize + 5] ^= 0x40;
+        assert_eq!(recovered("damaged", &bytes), vec![0, 66, 132]);
+    }
+
+    #[test]
+    fn recover_keeps_an_undamaged_file() {
+        let (bytes, _) = recording(3);
+        assert_eq!(recovered("whole", &bytes), vec![0, 33, 66]);
+    }
+
+    #[test]
+    fn recover_empty_recording() {
+        let mut bytes = Vec::new();
+        header().write(&mut bytes).unwrap();
+        assert!(recovered("empty", &bytes).is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+use std::fs::File;
+use std::io::{BufReader, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+use errors::NuiError;
+use format::{self, Header, Stream};
+
+/// A summary of a recording, read from its header and index
+/// without decoding any frames. Without an index the frames are
+/// scanned to rebuild it.
+#[derive(Clone, Debug)]
+pub struct RecordingInfo {
+    pub header: Header,
+    pub frame_count: usize,
+    /// From the first frame's time stamp to the last's.
+    pub duration: Duration,
+    /// The size of the whole file.
+    pub file_bytes: u64,
+    /// The encoded bytes of each stream in every frame, before compression.
+    pub stream_bytes: StreamBytes,
+    /// The most skeletons tracked in a single frame.
+    pub max_skeletons: usize,
+}
+
+/// Bytes used by each stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamBytes {
+    pub skeleton: u64,
+    pub depth: u64,
+    pub color: u64,
+    pub user: u64,
+}
+
+impl RecordingInfo {
+    /// The streams stored in the recording.
+    pub fn streams(&self) -> &[Stream] {
+        &self.header.streams
+    }
+
+    /// The rows and columns of the depth, color and user frames.
+    pub fn resolution(&self) -> (i32, i32) {
+        (self.header.rows, self.header.cols)
+    }
+}
+
+impl StreamBytes {
+    pub fn get(&self, stream: Stream) -> u64 {
+        match stream {
+            Stream::Skeleton => self.skeleton,
+            Stream::Depth => self.depth,
+            Stream::Color => self.color,
+            Stream::User => self.user,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.skeleton + self.depth + self.color + self.user
+    }
+}
+
+pub fn read_info(path: &Path) -> Result<RecordingInfo, NuiError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let header = Header::read(&mut reader)?;
+    let data_start = reader.seek(SeekFrom::Current(0))?;
+    let index = format::read_index(&mut reader, data_start)?;
+    let file_bytes = reader.seek(SeekFrom::End(0))?;
+    let duration = match (index.first(), index.last()) {
+        (Some(first), Some(last)) => Duration::from_micros(last.time_stamp.saturating_sub(first.time_stamp)),
+        _ => Duration::from_secs(0),
+    };
+    let mut stream_bytes = StreamBytes::default();
+    let mut max_skeletons = 0;
+    for entry in &index {
+        let sizes = entry.stats.sizes;
+        stream_bytes.skeleton += sizes[0] as u64;
+        stream_bytes.depth += sizes[1] as u64;
+        stream_bytes.color += sizes[2] as u64;
+        stream_bytes.user += sizes[3] as u64;
+        max_skeletons = max_skeletons.max(entry.stats.skeletons as usize);
+    }
+    Ok(RecordingInfo{
+        header,
+        frame_count: index.len(),
+        duration,
+        file_bytes,
+        stream_bytes,
+        max_skeletons,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use data::{DepthFrameFeed, FrameStamp, SkeletonDataFeed, SkeletonFeed};
+    use recorder::{Recorder, TimePoint};
+    use super::*;
+
+    /// Five frames 40ms apart with depth and up to three skeletons.
+    fn recording(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("nuitrack-rs-info-{}-{}.snap", name, process::id()));
+        let mut recorder = Recorder::builder()
+            .path(&path)
+            .streams(&[Stream::Skeleton, Stream::Depth])
+            .build().unwrap();
+        for i in 0..5 {
+            let time_stamp = i * 40_000;
+            let skeletons = (0..i.min(3) as i32).map(|id| SkeletonFeed{ id, joints: Vec::new() }).collect();
+            let depth = DepthFrameFeed{ rows: 2, cols: 3, stamp: FrameStamp{ id: i, time_stamp }, data: vec![i as u16; 6] };
+            recorder.push_frame(TimePoint{
+                skeleton: Some(SkeletonDataFeed{ time_stamp, skeletons }),
+                depth: Some(depth),
+                ..TimePoint::default()
+            }).unwrap();
+        }
+        recorder.flush().unwrap();
+        path
+    }
+
+    #[test]
+    fn summary_from_the_index() {
+        let path = recording("summary");
+        let info = read_info(&path).unwrap();
+        assert_eq!(info.frame_count, 5);
+        assert_eq!(info.duration, Duration::from_millis(160));
+        assert_eq!(info.streams(), &[Stream::Skeleton, Stream::Depth]);
+        assert_eq!(info.resolution(), (2, 3));
+        assert_eq!(info.max_skeletons, 3);
+        assert_eq!(info.file_bytes, fs::metadata(&path).unwrap().len());
+        let bytes = info.stream_bytes;
+        assert!(bytes.skeleton > 0 && bytes.depth > 0);
+        assert_eq!((bytes.color, bytes.user), (0, 0));
+        assert_eq!(bytes.total(), bytes.get(Stream::Skeleton) + bytes.get(Stream::Depth));
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn summary_without_an_index() {
+        let path = recording("unindexed");
+        let indexed = read_info(&path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+        // The frames are scanned to rebuild the same index
+        let info = read_info(&path).unwrap();
+        assert_eq!(info.frame_count, indexed.frame_count);
+        assert_eq!(info.duration, indexed.duration);
+        assert_eq!(info.stream_bytes, indexed.stream_bytes);
+        assert_eq!(info.max_skeletons, indexed.max_skeletons);
+        fs::remove_file(path).ok();
+    }
+}
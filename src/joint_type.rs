@@ -83,4 +83,35 @@ impl JointType {
         };
         Some(ty)
     }
+
+    /// A snake case name such as `left_shoulder`, used when exporting.
+    pub fn name(&self) -> &'static str {
+        match self {
+            JointType::None => "none",
+            JointType::Head => "head",
+            JointType::Neck => "neck",
+            JointType::Torso => "torso",
+            JointType::Waist => "waist",
+            JointType::LeftCollar => "left_collar",
+            JointType::LeftShoulder => "left_shoulder",
+            JointType::LeftElbow => "left_elbow",
+            JointType::LeftWrist => "left_wrist",
+            JointType::LeftHand => "left_hand",
+            JointType::LeftFingertip => "left_fingertip",
+            JointType::RightCollar => "right_collar",
+            JointType::RightShoulder => "right_shoulder",
+            JointType::RightElbow => "right_elbow",
+            JointType::RightWrist => "right_wrist",
+            JointType::RightHand => "right_hand",
+            JointType::RightFingertip => "right_fingertip",
+            JointType::LeftHip => "left_hip",
+            JointType::LeftKnee => "left_knee",
+            JointType::LeftAnkle => "left_ankle",
+            JointType::LeftFoot => "left_foot",
+            JointType::RightHip => "right_hip",
+            JointType::RightKnee => "right_knee",
+            JointType::RightAnkle => "right_ankle",
+            JointType::RightFoot => "right_foot",
+        }
+    }
 }
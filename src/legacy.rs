@@ -0,0 +1,108 @@
+//! Reads recordings made before the binary format.
+//!
+//! Each frame was written as a JSON `TimePoint` through its own snappy
+//! frame encoder, so every frame starts with a snappy stream identifier.
+//! Frames can be found by walking the snappy chunk headers
+//! without decompressing anything.
+//! The files have no time stamps so frames are spaced at `FRAME_MICROS`.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use serde_json::Deserializer;
+use snap;
+use codec::Codecs;
+use data::{color3_vec, FrameStamp, SkeletonFeed, SkeletonDataFeed, DepthFrameFeed, RGBFrameFeed};
+use errors::NuiError;
+use format::{self, FrameStats, Header, IndexEntry, Stream};
+use nui::tdv::nuitrack::Color3;
+use recorder::TimePoint;
+
+/// The first bytes of a snappy frame stream and so of every legacy frame.
+pub const STREAM_IDENTIFIER: &'static [u8; 10] = b"\xff\x06\x00\x00sNaPpY";
+
+/// The time between frames given to legacy recordings, 30 fps.
+pub const FRAME_MICROS: u64 = 33_333;
+
+/// One frame as the old `Recorder` wrote it.
+#[derive(Deserialize)]
+struct LegacyTimePoint {
+    skeleton: Vec<SkeletonFeed>,
+    rows: i32,
+    cols: i32,
+    depth: Vec<u16>,
+    #[serde(with = "color3_vec")]
+    color: Vec<Color3>,
+}
+
+impl LegacyTimePoint {
+    fn into_time_point(self, frame: usize) -> TimePoint {
+        let LegacyTimePoint{ skeleton, rows, cols, depth, color } = self;
+        let stamp = FrameStamp{ id: frame as u64, time_stamp: time_stamp(frame) };
+        TimePoint{
+            skeleton: Some(SkeletonDataFeed{ time_stamp: stamp.time_stamp, skeletons: skeleton }),
+            depth: Some(DepthFrameFeed{ rows, cols, stamp, data: depth }),
+            color: Some(RGBFrameFeed{ rows, cols, stamp, data: color }),
+            user: None,
+        }
+    }
+}
+
+/// Checks for the snappy stream identifier and rewinds.
+pub fn is_legacy<R: Read + Seek>(r: &mut R) -> io::Result<bool> {
+    let start = r.seek(SeekFrom::Current(0))?;
+    let mut magic = [0u8; 10];
+    let legacy = r.read_exact(&mut magic).is_ok() && &magic == STREAM_IDENTIFIER;
+    r.seek(SeekFrom::Start(start))?;
+    Ok(legacy)
+}
+
+/// Makes up a header from the first frame, as legacy files don't have one.
+pub fn read_header<R: Read + Seek>(r: &mut R) -> Result<Header, NuiError> {
+    r.seek(SeekFrom::Start(0))?;
+    let (rows, cols) = match read_frame(r, 0) {
+        Ok(frame) => frame.size().unwrap_or((0, 0)),
+        Err(_) => (0, 0),
+    };
+    let streams = vec![Stream::Skeleton, Stream::Depth, Stream::Color];
+    Ok(Header::new(streams, rows, cols, 0, Codecs::default(), 0, 0))
+}
+
+/// Finds where each frame starts. A frame cut short at the end of the file is left out.
+pub fn read_index<R: Read + Seek>(r: &mut R) -> io::Result<Vec<IndexEntry>> {
+    let file_len = r.seek(SeekFrom::End(0))?;
+    let mut index = Vec::new();
+    let mut offset = 0;
+    while offset + 4 <= file_len {
+        r.seek(SeekFrom::Start(offset))?;
+        let mut chunk = [0u8; 4];
+        r.read_exact(&mut chunk)?;
+        let len = chunk[1] as u64 | (chunk[2] as u64) << 8 | (chunk[3] as u64) << 16;
+        if offset + 4 + len > file_len {
+            index.pop();
+            break;
+        }
+        if chunk[0] == STREAM_IDENTIFIER[0] {
+            let time_stamp = time_stamp(index.len());
+            index.push(IndexEntry{ offset, time_stamp, stats: FrameStats::default() });
+        }
+        offset += 4 + len;
+    }
+    Ok(index)
+}
+
+/// Reads the frame at the reader's position.
+/// `frame` is its position in the file, used for its time stamp.
+pub fn read_frame<R: Read>(r: &mut R, frame: usize) -> Result<TimePoint, NuiError> {
+    let snappy = snap::Reader::new(r);
+    let legacy = Deserializer::from_reader(snappy)
+        .into_iter::<LegacyTimePoint>()
+        .next()
+        .ok_or_else(|| NuiError::Corrupt(format!("frame {} is missing", frame)))?
+        .map_err(|e| NuiError::Corrupt(e.to_string()))?
+        .into_time_point(frame);
+    format::check_frame(&legacy)?;
+    Ok(legacy)
+}
+
+fn time_stamp(frame: usize) -> u64 {
+    frame as u64 * FRAME_MICROS
+}
@@ -47,48 +47,55 @@
 //! // This is the same as above but with the following additions
 //!
 //! // Create a recorder
-//! let mut recorder = nuitrack_rs::record();
+//! // Use Recorder::builder() to choose the path, file name,
+//! // buffer size and which streams are recorded.
+//! let mut recorder = nuitrack_rs::record().expect("Failed to create recorder");
 //! 
 //! // Create a capture for skeleton data
 //! let skeleton_capture = recorder.new_capture();
 //!
 //! // Collect the skeleton data and make it owned.
 //! nui.skeleton_data(move |data| {
-//! let data = data.skeletons()
-//!         .iter()
-//!         .map(|s| s.make_owned())
-//!         .collect();
-//!     skeleton_capture.capture_skeleton(data);
+//!     // Captures only fail if the recorder has been stopped
+//!     skeleton_capture.capture_skeleton(data.make_owned()).ok();
 //! }).expect("Failed to add callback");
 //!
 //! // Create a capture for depth
 //! let depth_capture = recorder.new_capture();
 //!
-//! // Collect the depth data and make it an owned vec.
+//! // Collect the depth data and make it owned.
+//! // This keeps the size, frame id and time stamp.
 //! nui.depth_data(move |data| {
-//!     depth_capture.capture_depth(data.frame().to_vec());
+//!     depth_capture.capture_depth(data.make_owned()).ok();
 //! }).expect("Failed to add callback");
 //!
 //! // Create a capture for color
 //! let color_capture = recorder.new_capture();
 //!
-//! // Create rows and cols capture
-//! let size_capture = recorder.new_capture();
-//!
-//! // Collect the color data and make it an owned vec.
-//! // Collect the frame size
+//! // Collect the color data and make it owned.
 //! nui.color_data(move |data| {
-//!     size_capture.capture_size((data.rows, data.cols));
-//!     color_capture.capture_color(data.frame().to_vec());
+//!     color_capture.capture_color(data.make_owned()).ok();
 //! }).expect("Failed to add callback");
+//!
+//! // User frames are captured the same way with capture_user().
+//!
+//! // Every stream is recorded by default. To leave a stream out
+//! // skip its capture and list the others with Recorder::builder().streams().
+//!
+//! // Write on a background thread.
+//! // Data is only written to disk when a chunk of data is collected.
+//! // Use Recorder::builder() to set the capture queue size and choose
+//! // whether a full queue blocks or drops frames.
+//! // To use your own thread instead call write() after each frame
+//! // and flush() at the end.
+//! // Long recordings can be split into several files with
+//! // rotate_after_duration(), rotate_after_bytes() or rotate_after_frames().
+//! let recorder = recorder.spawn();
 //! # let nui: Nui<Running> = nui.run().expect("Failed to run nui");
-//! // Call write() after each frame.
-//! // Probably on a different thread.
-//! // This will collect data but only write to disk when a chunk of
-//! // data is collected
-//! recorder.write();
-//! // Call flush at the end incase there is some data that isn't wrtitten
-//! recorder.flush();
+//! // ...
+//! // Stop writes anything left and joins the thread
+//! println!("Dropped {} frames", recorder.dropped());
+//! recorder.stop().expect("Failed to stop recorder");
 //! ```
 //! ## Playback 
 //! ```rust
@@ -106,6 +113,7 @@
 //! // Create the nui player.
 //! // The second parameter is whether or not to loop the playback.
 //! // Looping is good if the recording is short.
+//! // The path can also be a directory of rotated recordings.
 //! let mut nui: Nui<Player> = nuitrack_rs::playback(path, false).expect("Couldn't create player");
 //!
 //! // All the callbacks are identical to live.
@@ -130,6 +138,12 @@
 //!
 //! // No call to run
 //!
+//! // Play at the recorded speed instead of as fast as possible.
+//! nui.set_speed(nuitrack_rs::Speed::Multiplier(1.0));
+//!
+//! // Know when the recording has been played through.
+//! nui.on_end(|| println!("Playback finished"));
+//!
 //! // Update is the same as live
 //! for _ in 0..100 {
 //!     nui.update().expect("Failed to update");
@@ -143,28 +157,51 @@ extern crate serde_json;
 extern crate serde_derive;
 
 extern crate snap;
+extern crate bincode;
+extern crate byteorder;
+extern crate crc32fast;
+extern crate image;
 
 mod callbacks;
+mod codec;
 mod data;
+mod edit;
 mod error_conversion;
 mod errors;
+mod export;
+mod format;
+mod info;
 mod joint_type;
+mod legacy;
 mod nui_import;
 mod player;
+mod queue;
 mod recorder;
+mod triggered;
 
 use error_conversion::NuiResult;
 use nui_import::root as nui;
 use std::marker::PhantomData;
-use std::path::PathBuf;
-use player::Content;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use player::{Content, Event, Pacer};
 pub use errors::NuiError;
 pub use nui::tdv::nuitrack::{Color3, Joint, Orientation, Vector3, BoundingBox, User};
 pub use nui::simple::{SkeletonData, DepthFrame, RGBFrame, Skeleton, UserFrame};
 pub use callbacks::CallBack;
 pub use joint_type::{JointType, SKELETON_BONES};
-pub use recorder::{Recorder, TimePoint};
-pub use self::data::{SkeletonFeed, feed_to_ptr};
+pub use recorder::{Recorder, RecorderBuilder, RecorderHandle, TimePoint};
+pub use triggered::{Trigger, TriggerHandle, TriggeredRecorder};
+pub use queue::Overflow;
+pub use player::{OnCorrupt, Speed};
+pub use format::{Header, Stream, FORMAT_VERSION};
+pub use info::{RecordingInfo, StreamBytes};
+pub use edit::VerifyReport;
+pub use export::write_c3d;
+pub use export::{BvhWriter, FileNaming, GltfWriter, ImageFormat, Intrinsics, PlyFormat, Point, PointCloud, SkeletonTable, TableFormat, TableLayout};
+pub use codec::{Codecs, ColorCodec, DepthCodec};
+pub use self::data::{SkeletonFeed, SkeletonDataFeed, DepthFrameFeed, RGBFrameFeed, UserFrameFeed, FrameStamp, feed_to_ptr};
 
 pub struct Nui<T> {
     state: T,
@@ -183,6 +220,22 @@ pub struct Running;
 pub struct Offline;
 pub struct Player {
     content: Content,
+    pacer: Pacer,
+    paused: bool,
+    on_wrap: Vec<Box<FnMut() -> () + Send + 'static>>,
+    on_end: Vec<Box<FnMut() -> () + Send + 'static>>,
+}
+
+impl Player {
+    fn new(content: Content) -> Self {
+        Player{
+            content,
+            pacer: Pacer::new(Speed::AsFastAsPossible),
+            paused: false,
+            on_wrap: Vec::new(),
+            on_end: Vec::new(),
+        }
+    }
 }
 
 enum CallBackHolder {
@@ -193,20 +246,126 @@ enum CallBackHolder {
     PSkeleton(Box<FnMut(SkeletonData) -> () + Send + 'static>),
     PDepth(Box<FnMut(DepthFrame) -> () + Send + 'static>),
     PColor(Box<FnMut(RGBFrame) -> () + Send + 'static>),
+    PUser(Box<FnMut(UserFrame) -> () + Send + 'static>),
 }
 
 pub fn init() -> Result<Nui<Initialized>, NuiError> {
     Nui::<Offline>::new()
 }
 
-pub fn record() -> Recorder {
+pub fn record() -> Result<Recorder, NuiError> {
     Recorder::new()
 }
 
+/// `path` can be a recording or a directory of rotated recordings.
+/// A directory plays the files of its latest session.
+/// Each legacy recording is a session of its own, and files with a
+/// damaged header are skipped.
 pub fn playback(path: PathBuf, loop_player: bool) -> Result<Nui<Player>, NuiError> {
     Nui::<Player>::new(path, loop_player)
 }
 
+/// Plays the files of one session from a directory of rotated recordings.
+/// The session is `Header::session`.
+pub fn playback_session(path: PathBuf, session: u64, loop_player: bool) -> Result<Nui<Player>, NuiError> {
+    Nui::<Player>::from_session(path, session, loop_player)
+}
+
+/// Plays the recordings one after another as a single session.
+pub fn playback_files(paths: Vec<PathBuf>, loop_player: bool) -> Result<Nui<Player>, NuiError> {
+    Nui::<Player>::from_files(paths, loop_player)
+}
+
+/// Summarises a recording from its header and index
+/// without decoding the frames.
+/// If the index is missing every frame is decompressed to rebuild it.
+pub fn recording_info(path: PathBuf) -> Result<RecordingInfo, NuiError> {
+    info::read_info(&path)
+}
+
+/// Rewrites a recording damaged by a crash or power cut so it plays
+/// and seeks normally. Returns the number of frames kept.
+pub fn recover(path: PathBuf) -> Result<usize, NuiError> {
+    format::recover(&path)
+}
+
+/// Copies the part of a recording between `from` and `to` after its start.
+/// Returns the number of frames written.
+pub fn trim_recording(input: PathBuf, output: PathBuf, from: Duration, to: Option<Duration>) -> Result<usize, NuiError> {
+    edit::trim(&input, &output, from, to)
+}
+
+/// Joins recordings with the same streams and resolution.
+/// Each one continues one frame after the end of the one before.
+/// Returns the number of frames written.
+pub fn concat_recordings(inputs: &[PathBuf], output: PathBuf) -> Result<usize, NuiError> {
+    let inputs: Vec<&Path> = inputs.iter().map(|p| p.as_path()).collect();
+    edit::concat(&inputs, &output)
+}
+
+/// Copies a recording without one of its streams.
+/// Returns the number of frames written.
+pub fn strip_stream(input: PathBuf, output: PathBuf, stream: Stream) -> Result<usize, NuiError> {
+    edit::strip_stream(&input, &output, stream)
+}
+
+/// Copies every `every`th frame of a recording.
+/// Returns the number of frames written.
+pub fn downsample_recording(input: PathBuf, output: PathBuf, every: usize) -> Result<usize, NuiError> {
+    edit::downsample(&input, &output, every)
+}
+
+/// Rewrites a recording made by the old JSON recorder in the current format.
+/// `playback` reads old recordings too, but decodes them much more slowly.
+/// Returns the number of frames written.
+pub fn transcode_legacy(input: PathBuf, output: PathBuf) -> Result<usize, NuiError> {
+    edit::transcode_legacy(&input, &output)
+}
+
+/// Decodes every frame of a recording and checks its index.
+/// Legacy recordings are checked frame by frame as they have no index.
+pub fn verify_recording(path: PathBuf) -> Result<VerifyReport, NuiError> {
+    edit::verify(&path)
+}
+
+/// Writes the skeletons of a recording as a CSV or JSON Lines table.
+/// Returns the number of frames written.
+pub fn export_skeleton_table(recording: PathBuf, output: PathBuf, format: TableFormat, layout: TableLayout) -> Result<usize, NuiError> {
+    export::export_table(&recording, &output, format, layout)
+}
+
+/// Writes the motion of one skeleton in a recording as BVH,
+/// sampled every `frame_time`. Returns the number of frames written.
+pub fn export_bvh(recording: PathBuf, output: PathBuf, skeleton_id: i32, frame_time: Duration) -> Result<usize, NuiError> {
+    export::export_bvh(&recording, &output, skeleton_id, frame_time)
+}
+
+/// Writes the motion of every skeleton in a recording as binary glTF,
+/// one animation per skeleton id. Returns the number of animations written.
+pub fn export_gltf(recording: PathBuf, output: PathBuf) -> Result<usize, NuiError> {
+    export::export_gltf(&recording, &output)
+}
+
+/// Writes the skeletons of a recording as C3D, with the frame rate
+/// taken from the time stamps. Returns the number of frames written.
+pub fn export_c3d(recording: PathBuf, output: PathBuf) -> Result<usize, NuiError> {
+    export::export_c3d(&recording, &output)
+}
+
+/// Saves the depth and color frames of a recording as images in `dir`.
+/// Returns the number of images written.
+pub fn export_images(recording: PathBuf, dir: PathBuf, format: ImageFormat, naming: FileNaming) -> Result<usize, NuiError> {
+    export::export_images(&recording, &dir, format, naming)
+}
+
+/// Saves a PLY point cloud for every depth frame of a recording in `dir`,
+/// colorized when the recording has color. Pass a user id to keep only
+/// that user's points, frames without user data are then skipped.
+/// Returns the number of clouds written.
+pub fn export_point_clouds(recording: PathBuf, dir: PathBuf, intrinsics: Intrinsics, format: PlyFormat, user: Option<i32>, naming: FileNaming) -> Result<usize, NuiError> {
+    export::export_point_clouds(&recording, &dir, &intrinsics, format, user, naming)
+}
+
 impl Nui<Offline> {
     pub fn new() -> Result<Nui<Initialized>, NuiError> {
         unsafe{
@@ -219,9 +378,115 @@ impl Nui<Offline> {
 
 impl Nui<Player> {
     pub fn new(path: PathBuf, loop_player: bool) -> Result<Nui<Player>, NuiError> {
-        let content = player::read_in(path, loop_player);
-        Ok(Nui{state: Player{content}, callbacks: Vec::new()})
+        let content = player::read_in(path, None, loop_player)?;
+        Ok(Nui{state: Player::new(content), callbacks: Vec::new()})
+    }
+
+    pub fn from_session(path: PathBuf, session: u64, loop_player: bool) -> Result<Nui<Player>, NuiError> {
+        let content = player::read_in(path, Some(session), loop_player)?;
+        Ok(Nui{state: Player::new(content), callbacks: Vec::new()})
+    }
+
+    pub fn from_files(paths: Vec<PathBuf>, loop_player: bool) -> Result<Nui<Player>, NuiError> {
+        let content = player::read_in_files(paths, loop_player)?;
+        Ok(Nui{state: Player::new(content), callbacks: Vec::new()})
+    }
+
+    /// The metadata stored at the start of the recording.
+    /// For a recording split over several files this is the first file's.
+    pub fn header(&self) -> &Header {
+        &self.state.content.header
+    }
+
+    /// Sets how fast `update` plays the recording.
+    ///
+    /// By default frames are returned as fast as possible.
+    /// Use `Speed::Multiplier(1.0)` to play at the recorded speed.
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.state.pacer.set_speed(speed);
+    }
+
+    /// Sets what `update` does when it reaches a damaged frame.
+    /// By default playback stops at the last good frame.
+    pub fn set_on_corrupt(&mut self, on_corrupt: OnCorrupt) {
+        self.state.content.set_on_corrupt(on_corrupt);
+    }
+
+    /// Stops `update` playing frames until `resume` is called.
+    /// While paused `update` sleeps for about one frame's time and returns
+    /// `Ok(())`, so a loop calling it doesn't spin.
+    pub fn pause(&mut self) {
+        self.state.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.state.paused = false;
+        // Don't rush to catch up on the time spent paused
+        self.state.pacer.reset();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.paused
+    }
+
+    /// Sets whether playback starts again after the last frame
+    /// instead of ending.
+    pub fn set_loop(&mut self, loop_player: bool) {
+        self.state.content.set_loop(loop_player);
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.state.content.is_looping()
+    }
+
+    /// Only plays the frames from `start` up to but not including `end`.
+    /// With `set_loop(true)` this is an A-B loop.
+    /// Playback moves to the start of the range if it is outside it.
+    pub fn set_loop_range(&mut self, start: usize, end: usize) -> Result<(), NuiError> {
+        self.state.content.set_range(start, end)?;
+        self.state.pacer.reset();
+        Ok(())
+    }
+
+    /// Plays the whole recording again.
+    pub fn clear_loop_range(&mut self) {
+        self.state.content.clear_range();
+    }
+
+    /// The frames played, `start..end`.
+    pub fn loop_range(&self) -> (usize, usize) {
+        self.state.content.range()
+    }
+
+    /// Changes direction from the frame last played, so the next `update`
+    /// plays the frame either side of it in the new direction.
+    /// If playback had ended it carries on back from the end it reached.
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.state.content.set_reverse(reverse);
+        self.state.pacer.set_reverse(reverse);
     }
+
+    pub fn is_reverse(&self) -> bool {
+        self.state.content.is_reverse()
+    }
+
+    /// Called after the last frame of a loop is played,
+    /// before playback starts again.
+    pub fn on_wrap<F>(&mut self, cb: F)
+        where
+        F: FnMut() -> () + Send + 'static
+        {
+            self.state.on_wrap.push(Box::new(cb));
+        }
+
+    /// Called once the last frame has been played.
+    /// The next `update` returns `NuiError::PlayBackEnd`.
+    pub fn on_end<F>(&mut self, cb: F)
+        where
+        F: FnMut() -> () + Send + 'static
+        {
+            self.state.on_end.push(Box::new(cb));
+        }
     
     pub fn skeleton_data<F>(&mut self, cb: F)
         -> Result<(), NuiError>
@@ -250,28 +515,108 @@ impl Nui<Player> {
             self.callbacks.push(CallBackHolder::PColor(Box::new(cb)));
             Ok(())
         }
+
+    pub fn user_data<F>(&mut self, cb: F)
+        -> Result<(), NuiError>
+        where
+        F: FnMut(UserFrame) -> () + Send + 'static
+        {
+            self.callbacks.push(CallBackHolder::PUser(Box::new(cb)));
+            Ok(())
+        }
     
     pub fn update(&mut self) -> Result<(), NuiError> {
-        if let Some(content) = self.state.content.next() {
-            let TimePoint {
-                skeleton,
-                mut depth,
-                mut color,
-                rows,
-                cols,
-            } = content;
-            let mut skeletons: Vec<Skeleton> = data::feed_to_ptr(&skeleton);
-            for cb in self.callbacks.iter_mut() {
-                match cb {
-                    CallBackHolder::PSkeleton(cb) => (*cb)((&mut skeletons).into()),
-                    CallBackHolder::PDepth(cb) => (*cb)((&mut depth, rows, cols).into()),
-                    CallBackHolder::PColor(cb) => (*cb)((&mut color, rows, cols).into()),
-                    _ => eprintln!("Wrong type of playback callback"),
-                }
-            }
+        if self.state.paused {
+            thread::sleep(self.state.content.frame_time());
+            return Ok(());
+        }
+        let result = if let Some(content) = self.state.content.next() {
+            let content = content?;
+            self.state.pacer.wait(content.time_stamp());
+            self.play(content);
             Ok(())
         } else { 
             Err(NuiError::PlayBackEnd)
+        };
+        self.fire_event();
+        result
+    }
+
+    fn fire_event(&mut self) {
+        let callbacks = match self.state.content.take_event() {
+            Some(Event::Wrapped) => &mut self.state.on_wrap,
+            Some(Event::Ended) => &mut self.state.on_end,
+            None => return,
+        };
+        for cb in callbacks.iter_mut() {
+            (*cb)()
+        }
+    }
+
+    /// The index of the frame the next call to `update` will play.
+    pub fn position(&self) -> usize {
+        self.state.content.position()
+    }
+
+    /// The number of frames in the recording.
+    pub fn frame_count(&self) -> usize {
+        self.state.content.len()
+    }
+
+    /// Makes `frame` the next frame `update` plays.
+    pub fn seek_to_frame(&mut self, frame: usize) -> Result<(), NuiError> {
+        self.state.content.seek(frame)?;
+        self.state.pacer.reset();
+        Ok(())
+    }
+
+    /// Makes the last frame captured at or before `time`
+    /// after the start of the recording the next frame `update` plays.
+    pub fn seek_to_time(&mut self, time: Duration) -> Result<(), NuiError> {
+        match self.state.content.frame_at(time) {
+            Some(frame) => self.seek_to_frame(frame),
+            None => Err(NuiError::SeekOutOfRange(0)),
+        }
+    }
+
+    /// Plays the frame before the one last played.
+    pub fn step_back(&mut self) -> Result<(), NuiError> {
+        let last = match self.state.content.last_read() {
+            Some(last) if last > 0 => last,
+            _ => return Err(NuiError::SeekOutOfRange(0)),
+        };
+        self.seek_to_frame(last - 1)?;
+        match self.state.content.next() {
+            Some(content) => {
+                self.play(content?);
+                self.fire_event();
+                Ok(())
+            },
+            None => Err(NuiError::PlayBackEnd),
+        }
+    }
+
+    fn play(&mut self, content: TimePoint) {
+        let TimePoint { skeleton, depth, color, mut user } = content;
+        let mut skeletons: Option<(Vec<Skeleton>, u64)> = skeleton
+            .map(|s| (data::feed_to_ptr(&s.skeletons), s.time_stamp));
+        for cb in self.callbacks.iter_mut() {
+            // Only call back for streams that are in this frame
+            match cb {
+                CallBackHolder::PSkeleton(cb) => if let Some((ref mut s, time_stamp)) = skeletons {
+                    (*cb)((s, time_stamp).into())
+                },
+                CallBackHolder::PDepth(cb) => if let Some(ref d) = depth {
+                    (*cb)(d.into())
+                },
+                CallBackHolder::PColor(cb) => if let Some(ref c) = color {
+                    (*cb)(c.into())
+                },
+                CallBackHolder::PUser(cb) => if let Some(ref mut u) = user {
+                    (*cb)(u.into())
+                },
+                _ => eprintln!("Wrong type of playback callback"),
+            }
         }
     }
 }
@@ -364,3 +709,111 @@ fn release_nui() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use super::*;
+
+    struct Played {
+        nui: Nui<Player>,
+        path: PathBuf,
+        frames: Arc<Mutex<Vec<u64>>>,
+        wraps: Arc<AtomicUsize>,
+        ends: Arc<AtomicUsize>,
+    }
+
+    impl Played {
+        /// The frames played since the last call.
+        fn frames(&self) -> Vec<u64> {
+            self.frames.lock().unwrap().drain(..).collect()
+        }
+    }
+
+    impl Drop for Played {
+        fn drop(&mut self) {
+            fs::remove_file(&self.path).ok();
+        }
+    }
+
+    /// Plays a recording of 10 skeleton frames 1ms apart,
+    /// noting the number of each frame played.
+    fn played(name: &str) -> Played {
+        let path = env::temp_dir().join(format!("nuitrack-rs-transport-{}-{}.snap", name, process::id()));
+        let mut recorder = Recorder::builder().path(&path).streams(&[Stream::Skeleton]).build().unwrap();
+        for i in 0..10 {
+            let skeleton = SkeletonDataFeed{ time_stamp: i * 1000, skeletons: Vec::new() };
+            recorder.push_frame(TimePoint{ skeleton: Some(skeleton), ..TimePoint::default() }).unwrap();
+        }
+        recorder.flush().unwrap();
+        let mut nui = playback(path.clone(), false).unwrap();
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let seen = frames.clone();
+        nui.skeleton_data(move |data| seen.lock().unwrap().push(data.time_stamp / 1000)).unwrap();
+        let wraps = Arc::new(AtomicUsize::new(0));
+        let count = wraps.clone();
+        nui.on_wrap(move || { count.fetch_add(1, Ordering::SeqCst); });
+        let ends = Arc::new(AtomicUsize::new(0));
+        let count = ends.clone();
+        nui.on_end(move || { count.fetch_add(1, Ordering::SeqCst); });
+        Played{ nui, path, frames, wraps, ends }
+    }
+
+    #[test]
+    fn pause_holds_playback() {
+        let mut p = played("pause");
+        p.nui.update().unwrap();
+        p.nui.update().unwrap();
+        p.nui.pause();
+        assert!(p.nui.is_paused());
+        for _ in 0..3 {
+            p.nui.update().unwrap();
+        }
+        assert_eq!(p.frames(), vec![0, 1]);
+        assert_eq!(p.nui.position(), 2);
+        p.nui.resume();
+        p.nui.update().unwrap();
+        assert_eq!(p.frames(), vec![2]);
+    }
+
+    #[test]
+    fn step_back_plays_the_frame_before() {
+        let mut p = played("step-back");
+        for _ in 0..3 {
+            p.nui.update().unwrap();
+        }
+        p.nui.step_back().unwrap();
+        p.nui.step_back().unwrap();
+        assert_eq!(p.frames(), vec![0, 1, 2, 1, 0]);
+        // There is nothing before the first frame
+        assert!(p.nui.step_back().is_err());
+        p.nui.update().unwrap();
+        assert_eq!(p.frames(), vec![1]);
+    }
+
+    #[test]
+    fn loop_range_wraps_then_ends() {
+        let mut p = played("loop-range");
+        p.nui.set_loop(true);
+        p.nui.set_loop_range(3, 6).unwrap();
+        assert_eq!(p.nui.loop_range(), (3, 6));
+        for _ in 0..7 {
+            p.nui.update().unwrap();
+        }
+        assert_eq!(p.frames(), vec![3, 4, 5, 3, 4, 5, 3]);
+        assert_eq!(p.wraps.load(Ordering::SeqCst), 2);
+        // Without looping it stops at the end of the range
+        p.nui.set_loop(false);
+        while p.nui.update().is_ok() {}
+        assert_eq!(p.frames(), vec![4, 5]);
+        assert_eq!(p.ends.load(Ordering::SeqCst), 1);
+        assert!(p.nui.set_loop_range(5, 5).is_err());
+        assert!(p.nui.set_loop_range(2, 11).is_err());
+        p.nui.clear_loop_range();
+        assert_eq!(p.nui.loop_range(), (0, 10));
+    }
+}
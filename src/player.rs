@@ -1,44 +1,832 @@
-use std::fs::File;
-use std::vec::IntoIter;
-use recorder::TimePoint;    
-use std::iter::Cycle;   
-use std::path::PathBuf; 
-use serde_json::Deserializer;
-use snap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Seek, SeekFrom};
+use recorder::TimePoint;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use errors::NuiError;
+use format::{self, Header, IndexEntry};
+use codec::Codecs;
+use legacy;
 
+/// The most frames decoded ahead of playback.
+/// This bounds the memory used by the player no matter how long the recording is.
+const READ_AHEAD: usize = 8;
+
+/// Streams frames from a recording.
+///
+/// A background thread decodes up to `READ_AHEAD` frames ahead
+/// of the current position. Seeking restarts it from the new position.
+/// A recording can be split over several files which are played back to back.
+/// Frames can be played in reverse and looped over part of the recording.
 pub struct Content {
-    data: Play,
+    /// The header of the first file.
+    pub header: Header,
+    index: Arc<Vec<Entry>>,
+    /// The next frame to be read.
+    position: usize,
+    /// The frame last read.
+    last: Option<usize>,
+    /// Playback has finished and won't wrap.
+    ended: bool,
+    plan: Plan,
+    /// Waiting to be taken by the player.
+    event: Option<Event>,
+    requests: Sender<Request>,
+    frames: Receiver<Decoded>,
+    /// Bumped on every seek so frames decoded before it can be skipped.
+    generation: u64,
+    /// The frame the read ahead thread will send next, if it is reading.
+    expected: Option<usize>,
+    on_corrupt: OnCorrupt,
+}
+
+/// What playback does when it reaches a damaged frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnCorrupt {
+    /// End playback as if the recording finished at the last good frame.
+    Stop,
+    /// Carry on from the next frame.
+    Skip,
+}
+
+/// One file of a recording.
+struct Part {
+    path: PathBuf,
+    codecs: Codecs,
+    /// Made before the binary format.
+    legacy: bool,
+}
+
+/// Where a frame is across all the files of a recording.
+#[derive(Clone, Copy)]
+struct Entry {
+    part: usize,
+    offset: u64,
+    time_stamp: u64,
+}
+
+/// Something that happened to playback while reading a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Looped back to the start of the range, or its end when in reverse.
+    Wrapped,
+    /// Reached the last frame to be played.
+    Ended,
+}
+
+/// Which frames are played and in what order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Plan {
+    /// Frames from `start` up to but not including `end`.
+    start: usize,
+    end: usize,
+    looping: bool,
+    reverse: bool,
+}
+
+impl Plan {
+    /// The frame played after `frame` and whether playback wrapped to get there.
+    fn step(&self, frame: usize) -> Option<(usize, bool)> {
+        if self.reverse {
+            if frame > self.start {
+                Some((frame - 1, false))
+            } else if self.looping {
+                Some((self.end - 1, true))
+            } else {
+                None
+            }
+        } else if frame + 1 < self.end {
+            Some((frame + 1, false))
+        } else if self.looping {
+            Some((self.start, true))
+        } else {
+            None
+        }
+    }
+
+    /// The frame playback starts from.
+    fn first(&self) -> usize {
+        if self.reverse {
+            self.end - 1
+        } else {
+            self.start
+        }
+    }
 }
 
-enum Play {
-    Straight(IntoIter<TimePoint>),
-    Loop(Cycle<IntoIter<TimePoint>>),
+/// Start decoding from a frame and carry on as planned.
+struct Request {
+    generation: u64,
+    frame: usize,
+    plan: Plan,
+}
+
+struct Decoded {
+    generation: u64,
+    frame: usize,
+    content: Result<TimePoint, NuiError>,
+}
+
+/// How fast a recording is played back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    /// Wait between frames using the recorded time stamps.
+    /// `1.0` is the original speed, `0.25` is quarter speed
+    /// and `2.0` is double speed.
+    /// Values of zero or less are treated as `AsFastAsPossible`.
+    Multiplier(f64),
+    /// Don't wait between frames. Useful for batch processing.
+    AsFastAsPossible,
+}
+
+/// Sleeps until a frame is due according to its time stamp.
+pub struct Pacer {
+    speed: Speed,
+    /// Time stamps go down as frames are played.
+    reverse: bool,
+    /// The time a frame was played and its time stamp.
+    anchor: Option<(Instant, u64)>,
 }
 
 impl Iterator for Content {
-    type Item = TimePoint;
+    type Item = Result<TimePoint, NuiError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.data {
-            Play::Straight(ref mut d) => d.next(),
-            Play::Loop(ref mut d) => d.next(),
+        let mut skipped = 0;
+        let mut wrapped = false;
+        loop {
+            if self.ended || self.index.is_empty() {
+                return None;
+            }
+            if self.expected != Some(self.position) {
+                self.generation += 1;
+                let request = Request{ generation: self.generation, frame: self.position, plan: self.plan };
+                if self.requests.send(request).is_err() {
+                    return Some(Err(NuiError::Disconnected));
+                }
+                self.expected = Some(self.position);
+            }
+            let decoded = loop {
+                match self.frames.recv() {
+                    Ok(ref d) if d.generation != self.generation || d.frame != self.position => (),
+                    Ok(d) => break d,
+                    Err(_) => return Some(Err(NuiError::Disconnected)),
+                }
+            };
+            match decoded.content {
+                Ok(content) => {
+                    let frame = self.position;
+                    self.last = Some(frame);
+                    self.advance(frame);
+                    return Some(Ok(content));
+                },
+                Err(NuiError::Corrupt(_)) if self.on_corrupt == OnCorrupt::Skip => {
+                    skipped += 1;
+                    // Every frame is damaged
+                    if skipped > self.plan.end - self.plan.start {
+                        return None;
+                    }
+                    let frame = self.position;
+                    self.advance(frame);
+                    // The thread stops after an error so ask for the next frame
+                    self.expected = None;
+                },
+                Err(NuiError::Corrupt(_)) => {
+                    // Treat the rest of the recording as missing
+                    self.expected = None;
+                    // Unless the frame wrapped to is damaged too, as it always would be
+                    if self.plan.looping && !wrapped {
+                        wrapped = true;
+                        self.position = self.plan.first();
+                        self.event = Some(Event::Wrapped);
+                    } else {
+                        self.ended = true;
+                        self.event = Some(Event::Ended);
+                        return None;
+                    }
+                },
+                Err(e) => {
+                    // The thread stops after an error so ask again next time
+                    self.expected = None;
+                    return Some(Err(e));
+                },
+            }
         }
     }
 }
 
-pub fn read_in(path: PathBuf, loop_player: bool) -> Content {
-    let file = File::open(path).expect("Couldn't open recording file");
-    let snappy = snap::Reader::new(file);
-    let content: Vec<TimePoint> = Deserializer::from_reader(snappy)
-        .into_iter::<TimePoint>()
-        .map(Result::unwrap)
-        .collect();
+impl Content {
+    /// The index of the next frame to be read.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The index of the frame last read.
+    pub fn last_read(&self) -> Option<usize> {
+        self.last
+    }
 
-    let data = if loop_player {
-        Play::Loop(content.into_iter().cycle())
-    } else {
-        Play::Straight(content.into_iter())
+    /// Moves past `frame` as planned. The read ahead thread does the same.
+    fn advance(&mut self, frame: usize) {
+        match self.plan.step(frame) {
+            Some((next, wrapped)) => {
+                self.position = next;
+                self.expected = Some(next);
+                if wrapped {
+                    self.event = Some(Event::Wrapped);
+                }
+            },
+            None => {
+                self.position = if self.plan.reverse { frame } else { frame + 1 };
+                self.expected = None;
+                self.ended = true;
+                self.event = Some(Event::Ended);
+            },
+        }
+    }
+
+    /// Takes what last happened to playback, if anything.
+    pub fn take_event(&mut self) -> Option<Event> {
+        self.event.take()
+    }
+
+    /// Changes the plan, the read ahead thread is restarted on the next read.
+    fn replan(&mut self, plan: Plan) {
+        self.plan = plan;
+        self.expected = None;
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.plan.looping
+    }
+
+    pub fn set_loop(&mut self, looping: bool) {
+        let plan = Plan{ looping, ..self.plan };
+        self.replan(plan);
+    }
+
+    /// The frames played, `start..end`.
+    pub fn range(&self) -> (usize, usize) {
+        (self.plan.start, self.plan.end)
+    }
+
+    /// Only plays the frames from `start` up to but not including `end`.
+    /// Playback moves to the start of the range if it is outside it.
+    pub fn set_range(&mut self, start: usize, end: usize) -> Result<(), NuiError> {
+        if end > self.index.len() {
+            return Err(NuiError::SeekOutOfRange(end));
+        }
+        if start >= end {
+            return Err(NuiError::SeekOutOfRange(start));
+        }
+        let plan = Plan{ start, end, ..self.plan };
+        self.replan(plan);
+        if self.ended || self.position < start || self.position >= end {
+            self.position = plan.first();
+            self.ended = false;
+        }
+        Ok(())
+    }
+
+    pub fn clear_range(&mut self) {
+        let plan = Plan{ start: 0, end: self.index.len(), ..self.plan };
+        self.replan(plan);
+    }
+
+    pub fn is_reverse(&self) -> bool {
+        self.plan.reverse
+    }
+
+    /// Plays the frames in the other direction from the frame last read,
+    /// so the frame after it in the new direction is read next.
+    /// After a seek it plays from the frame sought instead.
+    /// If playback had ended it carries on back from the end it reached.
+    pub fn set_reverse(&mut self, reverse: bool) {
+        let old = self.plan;
+        let plan = Plan{ reverse, ..self.plan };
+        self.replan(plan);
+        if self.index.is_empty() {
+            return;
+        }
+        // Turn around on the frame last read, unless playback moved off it with a seek
+        let turn_at = self.last.filter(|&last| {
+            reverse != old.reverse
+                && last >= plan.start
+                && last < plan.end
+                && (self.ended || old.step(last).map(|(next, _)| next) == Some(self.position))
+        });
+        match turn_at {
+            Some(last) => match plan.step(last) {
+                Some((next, wrapped)) => {
+                    self.position = next;
+                    self.ended = false;
+                    if wrapped {
+                        self.event = Some(Event::Wrapped);
+                    }
+                },
+                // Already at the end it is turning towards
+                None => {
+                    self.position = last;
+                    self.ended = true;
+                },
+            },
+            None => if self.ended {
+                self.position = plan.first();
+                self.ended = false;
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn set_on_corrupt(&mut self, on_corrupt: OnCorrupt) {
+        self.on_corrupt = on_corrupt;
+    }
+
+    pub fn seek(&mut self, frame: usize) -> Result<(), NuiError> {
+        if frame >= self.index.len() {
+            return Err(NuiError::SeekOutOfRange(frame));
+        }
+        self.position = frame;
+        self.ended = false;
+        Ok(())
+    }
+
+    /// The average time between frames, or 30fps with fewer than two frames.
+    pub fn frame_time(&self) -> Duration {
+        let micros = match (self.index.first(), self.index.last()) {
+            (Some(first), Some(last)) if last.time_stamp > first.time_stamp => {
+                (last.time_stamp - first.time_stamp) / (self.index.len() as u64 - 1)
+            },
+            _ => legacy::FRAME_MICROS,
+        };
+        Duration::from_micros(micros)
+    }
+
+    /// Finds the last frame captured at or before `time`
+    /// after the start of the recording.
+    pub fn frame_at(&self, time: Duration) -> Option<usize> {
+        let start = self.index.first()?.time_stamp;
+        let target = start + format::micros(time);
+        match self.index.binary_search_by_key(&target, |e| e.time_stamp) {
+            Ok(i) => Some(i),
+            Err(0) => Some(0),
+            Err(i) => Some(i - 1),
+        }
+    }
+}
+
+/// Reads a recording file, or the files of one session in a directory
+/// ordered by part. Sessions are separate recordings so only `session`
+/// is played, or the latest one if it is `None`.
+///
+/// Legacy recordings don't know their session so each one is a session
+/// of its own, numbered by when the file was last modified.
+/// Files that aren't recordings, or whose header is damaged, are skipped.
+pub fn read_in(path: PathBuf, session: Option<u64>, loop_player: bool) -> Result<Content, NuiError> {
+    if !path.is_dir() {
+        return read_in_files(vec![path], loop_player);
+    }
+    let mut recordings = Vec::new();
+    for entry in fs::read_dir(&path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            continue;
+        }
+        let (header, index, legacy) = match open(&path) {
+            Ok(opened) => opened,
+            // Anything else in the directory isn't ours, or can't be played
+            Err(NuiError::NotARecording) | Err(NuiError::Corrupt(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        // Each legacy recording is its own session
+        let key = if legacy {
+            let modified = fs::metadata(&path)?.modified()?;
+            let secs = modified.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            (secs, Some(path.clone()))
+        } else {
+            (header.session, None)
+        };
+        recordings.push((key, (path, header, index, legacy)));
+    }
+    let chosen = recordings.iter()
+        .map(|r| &r.0)
+        .filter(|key| session.map_or(true, |session| key.0 == session))
+        .max()
+        .cloned();
+    let chosen = match (chosen, session) {
+        (Some(chosen), _) => chosen,
+        (None, Some(session)) => return Err(io::Error::new(io::ErrorKind::NotFound, format!("no recordings of session {} found", session)).into()),
+        (None, None) => return Err(io::Error::new(io::ErrorKind::NotFound, "no recordings found").into()),
     };
-    Content{ data }
+    let mut opened: Vec<_> = recordings.into_iter()
+        .filter(|r| r.0 == chosen)
+        .map(|r| r.1)
+        .collect();
+    opened.sort_by(|a, b| (a.1.part, &a.0).cmp(&(b.1.part, &b.0)));
+    from_opened(opened, loop_player)
+}
+
+/// Plays the files one after another as a single recording.
+pub fn read_in_files(paths: Vec<PathBuf>, loop_player: bool) -> Result<Content, NuiError> {
+    let mut opened = Vec::with_capacity(paths.len());
+    for path in paths {
+        let (header, index, legacy) = open(&path)?;
+        opened.push((path, header, index, legacy));
+    }
+    from_opened(opened, loop_player)
+}
+
+/// Plays files that have already been opened, in order.
+fn from_opened(opened: Vec<(PathBuf, Header, Vec<IndexEntry>, bool)>, loop_player: bool) -> Result<Content, NuiError> {
+    let mut header = None;
+    let mut parts = Vec::with_capacity(opened.len());
+    let mut index = Vec::new();
+    for (path, file_header, file_index, legacy) in opened {
+        let part = parts.len();
+        index.extend(file_index
+                     .into_iter()
+                     .map(|IndexEntry{ offset, time_stamp, .. }| Entry{ part, offset, time_stamp }));
+        parts.push(Part{ path, codecs: file_header.codecs, legacy });
+        header.get_or_insert(file_header);
+    }
+    let header = header.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no recordings found"))?;
+    let plan = Plan{ start: 0, end: index.len(), looping: loop_player, reverse: false };
+    let index = Arc::new(index);
+    let (requests, requests_rx) = channel();
+    let (frames_tx, frames) = sync_channel(READ_AHEAD);
+    let thread_index = index.clone();
+    thread::spawn(move || read_ahead(parts, thread_index, requests_rx, frames_tx));
+    Ok(Content{
+        header,
+        index,
+        position: 0,
+        last: None,
+        ended: false,
+        plan,
+        event: None,
+        requests,
+        frames,
+        generation: 0,
+        expected: None,
+        on_corrupt: OnCorrupt::Stop,
+    })
+}
+
+/// Reads the header and index of a recording in either format.
+/// Also returns whether it is a legacy recording.
+fn open(path: &Path) -> Result<(Header, Vec<IndexEntry>, bool), NuiError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if legacy::is_legacy(&mut reader)? {
+        let header = legacy::read_header(&mut reader)?;
+        let index = legacy::read_index(&mut reader)?;
+        return Ok((header, index, true));
+    }
+    let header = Header::read(&mut reader)?;
+    let data_start = reader.seek(SeekFrom::Current(0))?;
+    let index = format::read_index(&mut reader, data_start)?;
+    Ok((header, index, false))
+}
+
+/// Decodes frames in the planned order from the latest requested frame
+/// until `frames` is full, then waits for room or a new request.
+fn read_ahead(
+    parts: Vec<Part>,
+    index: Arc<Vec<Entry>>,
+    requests: Receiver<Request>,
+    frames: SyncSender<Decoded>)
+{
+    // The open file and where the reader is in it, if known, so sequential reads don't seek
+    let mut reader: Option<(usize, BufReader<File>)> = None;
+    let mut reader_pos = None;
+    let mut next: Option<Request> = None;
+    loop {
+        // Only wait for a request when there is nothing left to decode
+        let request = if next.is_none() {
+            match requests.recv() {
+                Ok(r) => Some(r),
+                Err(_) => return,
+            }
+        } else {
+            match requests.try_recv() {
+                Ok(r) => Some(r),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        };
+        if let Some(r) = request {
+            next = Some(requests.try_iter().last().unwrap_or(r));
+        }
+        let Request{ generation, frame, plan } = match next.take() {
+            Some(r) => r,
+            None => continue,
+        };
+        if frame >= index.len() {
+            continue;
+        }
+        let content = read_frame_at(&parts, &mut reader, &mut reader_pos, &index, frame);
+        let failed = content.is_err();
+        if frames.send(Decoded{ generation, frame, content }).is_err() {
+            return;
+        }
+        if !failed {
+            // Looping rewinds instead of keeping the whole recording around
+            next = plan.step(frame).map(|(frame, _)| Request{ generation, frame, plan });
+        }
+    }
+}
+
+fn read_frame_at(
+    parts: &[Part],
+    reader: &mut Option<(usize, BufReader<File>)>,
+    reader_pos: &mut Option<u64>,
+    index: &[Entry],
+    frame: usize) -> Result<TimePoint, NuiError>
+{
+    let Entry{ part, offset, time_stamp } = index[frame];
+    // Only known again once this frame has been read
+    let mut pos = reader_pos.take();
+    if reader.as_ref().map(|r| r.0) != Some(part) {
+        pos = None;
+        *reader = Some((part, BufReader::new(File::open(&parts[part].path)?)));
+    }
+    let file = &mut reader.as_mut().expect("The reader was just opened").1;
+    // The next frame in the index isn't always next in the file,
+    // a rebuilt index leaves out damaged frames
+    if Some(offset) != pos {
+        file.seek(SeekFrom::Start(offset))?;
+    }
+    if parts[part].legacy {
+        // The snappy reader reads ahead so the position isn't known
+        return legacy::read_frame(file, (time_stamp / legacy::FRAME_MICROS) as usize);
+    }
+    let (content, len) = format::read_sized_frame(file, &parts[part].codecs)?
+        .ok_or_else(|| NuiError::Corrupt(format!("frame {} is missing", frame)))?;
+    *reader_pos = Some(offset + len);
+    Ok(content)
+}
+
+impl Pacer {
+    pub fn new(speed: Speed) -> Self {
+        Pacer{ speed, reverse: false, anchor: None }
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+        self.anchor = None;
+    }
+
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.reverse = reverse;
+        self.anchor = None;
+    }
+
+    /// Stops the next frame waiting on the previous one.
+    pub fn reset(&mut self) {
+        self.anchor = None;
+    }
 
+    /// Blocks until the frame with this time stamp (in microseconds) is due.
+    pub fn wait(&mut self, time_stamp: u64) {
+        let multiplier = match self.speed {
+            Speed::Multiplier(m) if m > 0.0 => m,
+            _ => return,
+        };
+        let elapsed = match self.anchor {
+            Some((start, start_stamp)) if !self.reverse && time_stamp >= start_stamp => Some((start, time_stamp - start_stamp)),
+            Some((start, start_stamp)) if self.reverse && time_stamp <= start_stamp => Some((start, start_stamp - time_stamp)),
+            _ => None,
+        };
+        let (start, elapsed) = match elapsed {
+            Some(elapsed) => elapsed,
+            // First frame or time jumped the wrong way because the recording looped
+            None => {
+                self.anchor = Some((Instant::now(), time_stamp));
+                return;
+            },
+        };
+        let micros = elapsed as f64 / multiplier;
+        let due = start + Duration::from_micros(micros as u64);
+        let now = Instant::now();
+        if due > now {
+            thread::sleep(due - now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::process;
+    use byteorder::{ByteOrder, LittleEndian};
+    use data::{DepthFrameFeed, FrameStamp};
+    use format::FRAME_MAGIC;
+    use recorder::Recorder;
+    use super::*;
+
+    /// A recording of `count` depth frames 33ms apart, with frame `damaged` broken.
+    fn recording(name: &str, count: u64, damaged: Option<usize>) -> PathBuf {
+        let path = env::temp_dir().join(format!("nuitrack-rs-player-{}-{}.snap", name, process::id()));
+        let mut recorder = Recorder::builder().path(&path).build().unwrap();
+        for i in 0..count {
+            let stamp = FrameStamp{ id: i, time_stamp: i * 33_000 };
+            let depth = DepthFrameFeed{ rows: 1, cols: 2, stamp, data: vec![i as u16, 1] };
+            recorder.push_frame(TimePoint{ depth: Some(depth), ..TimePoint::default() }).unwrap();
+        }
+        recorder.flush().unwrap();
+        if let Some(damaged) = damaged {
+            let mut bytes = fs::read(&path).unwrap();
+            let frame = bytes.windows(FRAME_MAGIC.len())
+                .enumerate()
+                .filter(|&(_, w)| w == FRAME_MAGIC)
+                .nth(damaged)
+                .unwrap()
+                .0;
+            // The first byte of the frame's data
+            bytes[frame + 20] ^= 0xff;
+            fs::write(&path, bytes).unwrap();
+        }
+        path
+    }
+
+    /// A legacy recording of `count` frames whose depth is `value`.
+    fn legacy_recording(path: &Path, count: usize, value: u16) {
+        let mut file = File::create(path).unwrap();
+        for _ in 0..count {
+            let black = r#"{"red":0,"green":0,"blue":0}"#;
+            let json = format!(r#"{{"skeleton":[],"rows":1,"cols":2,"depth":[{0},{0}],"color":[{1},{1}]}}"#, value, black);
+            // Every frame had its own snappy stream
+            let mut snappy = snap::Writer::new(&mut file);
+            snappy.write_all(json.as_bytes()).unwrap();
+            snappy.flush().unwrap();
+        }
+    }
+
+    fn directory(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("nuitrack-rs-player-{}-{}", name, process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    fn read_depths(content: &mut Content) -> Vec<u16> {
+        content.map(|f| f.unwrap().depth.unwrap().data[0]).collect()
+    }
+
+    fn read_stamps(content: &mut Content, frames: usize) -> Vec<u64> {
+        content.by_ref().take(frames).map(|f| f.unwrap().time_stamp() / 33_000).collect()
+    }
+
+    #[test]
+    fn stop_at_damaged_frame() {
+        let path = recording("stop", 4, Some(2));
+        let mut content = read_in(path.clone(), None, false).unwrap();
+        assert_eq!(read_stamps(&mut content, 10), vec![0, 1]);
+        assert_eq!(content.take_event(), Some(Event::Ended));
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn loop_before_damaged_frame() {
+        let path = recording("loop", 4, Some(2));
+        let mut content = read_in(path.clone(), None, true).unwrap();
+        assert_eq!(read_stamps(&mut content, 5), vec![0, 1, 0, 1, 0]);
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn loop_with_damaged_first_frame_ends() {
+        let path = recording("first", 4, Some(0));
+        let mut content = read_in(path.clone(), None, true).unwrap();
+        assert!(content.next().is_none());
+        assert_eq!(content.take_event(), Some(Event::Ended));
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn skip_damaged_frame() {
+        let path = recording("skip", 4, Some(1));
+        let mut content = read_in(path.clone(), None, false).unwrap();
+        content.set_on_corrupt(OnCorrupt::Skip);
+        assert_eq!(read_stamps(&mut content, 10), vec![0, 2, 3]);
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn skip_frame_with_damaged_length() {
+        let path = recording("length", 4, None);
+        let mut bytes = fs::read(&path).unwrap();
+        let frame = bytes.windows(FRAME_MAGIC.len()).position(|w| w == FRAME_MAGIC).unwrap();
+        // Long enough to run off the end of the file
+        let len = bytes.len() as u32;
+        LittleEndian::write_u32(&mut bytes[frame + 4..frame + 8], len);
+        fs::write(&path, bytes).unwrap();
+        let mut content = read_in(path.clone(), None, false).unwrap();
+        content.set_on_corrupt(OnCorrupt::Skip);
+        assert_eq!(read_stamps(&mut content, 10), vec![1, 2, 3]);
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn reverse_from_the_frame_last_read() {
+        let path = recording("reverse", 8, None);
+        let mut content = read_in(path.clone(), None, false).unwrap();
+        assert_eq!(read_stamps(&mut content, 6), vec![0, 1, 2, 3, 4, 5]);
+        content.set_reverse(true);
+        assert_eq!(read_stamps(&mut content, 3), vec![4, 3, 2]);
+        content.set_reverse(false);
+        assert_eq!(read_stamps(&mut content, 2), vec![3, 4]);
+        // Turning at the end carries on back from it
+        assert_eq!(read_stamps(&mut content, 10), vec![5, 6, 7]);
+        content.set_reverse(true);
+        assert_eq!(read_stamps(&mut content, 2), vec![6, 5]);
+        // A seek wins over the frame last read
+        content.seek(2).unwrap();
+        content.set_reverse(false);
+        assert_eq!(read_stamps(&mut content, 2), vec![2, 3]);
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn frame_time_from_time_stamps() {
+        let path = recording("frame-time", 4, None);
+        let content = read_in(path.clone(), None, false).unwrap();
+        assert_eq!(content.frame_time(), Duration::from_millis(33));
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn play_around_damaged_frame_without_index() {
+        let path = recording("unindexed", 5, Some(2));
+        let mut bytes = fs::read(&path).unwrap();
+        // Break the footer so the index is rebuilt without the damaged frame
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+        let mut content = read_in(path.clone(), None, false).unwrap();
+        assert_eq!(content.len(), 4);
+        assert_eq!(read_stamps(&mut content, 10), vec![0, 1, 3, 4]);
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn directory_of_legacy_recordings_plays_one() {
+        let dir = directory("legacy");
+        legacy_recording(&dir.join("a.snap"), 2, 1);
+        legacy_recording(&dir.join("b.snap"), 3, 2);
+        let mut content = read_in(dir.clone(), None, false).unwrap();
+        // The one written last, not both back to back
+        assert_eq!(read_depths(&mut content), vec![2, 2, 2]);
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn directory_skips_damaged_header() {
+        let dir = directory("damaged-header");
+        let good = recording("directory-good", 3, None);
+        fs::rename(&good, dir.join("good.snap")).unwrap();
+        let cut = recording("directory-cut", 3, None);
+        let bytes = fs::read(&cut).unwrap();
+        fs::write(dir.join("cut.snap"), &bytes[..14]).unwrap();
+        fs::remove_file(cut).ok();
+        let mut content = read_in(dir.clone(), None, false).unwrap();
+        assert_eq!(read_stamps(&mut content, 10), vec![0, 1, 2]);
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn seek_across_parts() {
+        let dir = directory("parts");
+        let mut recorder = Recorder::builder().path(dir.join("parts.snap")).rotate_after_frames(4).build().unwrap();
+        for i in 0..10 {
+            let stamp = FrameStamp{ id: i, time_stamp: i * 33_000 };
+            let depth = DepthFrameFeed{ rows: 1, cols: 2, stamp, data: vec![i as u16, 1] };
+            recorder.push_frame(TimePoint{ depth: Some(depth), ..TimePoint::default() }).unwrap();
+        }
+        recorder.flush().unwrap();
+        assert_eq!(recorder.files().len(), 3);
+        let mut content = read_in_files(recorder.files().to_vec(), false).unwrap();
+        assert_eq!(content.len(), 10);
+        assert_eq!(content.frame_at(Duration::from_millis(0)), Some(0));
+        assert_eq!(content.frame_at(Duration::from_millis(33 * 5)), Some(5));
+        assert_eq!(content.frame_at(Duration::from_millis(33 * 5 + 20)), Some(5));
+        assert_eq!(content.frame_at(Duration::from_secs(60)), Some(9));
+        // Frames are numbered through the whole session
+        content.seek(content.frame_at(Duration::from_millis(33 * 6)).unwrap()).unwrap();
+        assert_eq!(read_stamps(&mut content, 10), vec![6, 7, 8, 9]);
+        content.seek(2).unwrap();
+        assert_eq!(read_stamps(&mut content, 4), vec![2, 3, 4, 5]);
+        match content.seek(10) {
+            Err(NuiError::SeekOutOfRange(10)) => (),
+            other => panic!("expected SeekOutOfRange, got {:?}", other.err()),
+        }
+        fs::remove_dir_all(dir).ok();
+    }
 }
@@ -0,0 +1,196 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use errors::NuiError;
+
+/// What a capture does when its queue is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Wait for the recorder to catch up.
+    /// No data is lost but the nuitrack callbacks are held up.
+    Block,
+    /// Throw away the oldest queued frame to make room.
+    DropOldest,
+    /// Throw away the frame being captured.
+    DropNewest,
+}
+
+/// A bounded queue that can drop frames instead of blocking.
+///
+/// Items are numbered in the order they are pushed, counting the dropped
+/// ones, so queues that are fed together can be lined up again after one
+/// of them drops.
+pub struct Queue<T> {
+    state: Mutex<State<T>>,
+    /// Signalled whenever an item is added or removed or the queue closes.
+    changed: Condvar,
+    capacity: usize,
+    overflow: Overflow,
+}
+
+struct State<T> {
+    items: VecDeque<(u64, T)>,
+    /// The number of items pushed, including dropped ones.
+    pushed: u64,
+    dropped: u64,
+    closed: bool,
+}
+
+impl<T> Queue<T> {
+    pub fn new(capacity: usize, overflow: Overflow) -> Self {
+        let capacity = capacity.max(1);
+        Queue {
+            state: Mutex::new(State{ items: VecDeque::with_capacity(capacity), pushed: 0, dropped: 0, closed: false }),
+            changed: Condvar::new(),
+            capacity,
+            overflow,
+        }
+    }
+
+    /// Fails once the queue is closed.
+    pub fn push(&self, item: T) -> Result<(), NuiError> {
+        let mut state = self.state.lock().map_err(|_| NuiError::Disconnected)?;
+        loop {
+            if state.closed {
+                return Err(NuiError::Disconnected);
+            }
+            if state.items.len() < self.capacity {
+                break;
+            }
+            match self.overflow {
+                Overflow::Block => {
+                    state = self.changed.wait(state).map_err(|_| NuiError::Disconnected)?;
+                },
+                Overflow::DropOldest => {
+                    state.items.pop_front();
+                    state.dropped += 1;
+                    break;
+                },
+                Overflow::DropNewest => {
+                    state.pushed += 1;
+                    state.dropped += 1;
+                    return Ok(());
+                },
+            }
+        }
+        let number = state.pushed;
+        state.items.push_back((number, item));
+        state.pushed += 1;
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Blocks until there is an item and returns it with its number.
+    /// Returns `None` once the queue is closed and empty.
+    pub fn pop(&self) -> Option<(u64, T)> {
+        let mut state = self.state.lock().ok()?;
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                self.changed.notify_all();
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.changed.wait(state).ok()?;
+        }
+    }
+
+    /// Wakes anything waiting on the queue. Items already queued can still be popped.
+    pub fn close(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.closed = true;
+        }
+        self.changed.notify_all();
+    }
+
+    /// Counts a popped item that was thrown away as dropped.
+    pub fn discard(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.dropped += 1;
+        }
+    }
+
+    /// The number of items thrown away because the queue was full
+    /// or passed to `discard`.
+    pub fn dropped(&self) -> u64 {
+        self.state.lock().map(|s| s.dropped).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn drop_oldest_at_capacity() {
+        let queue = Queue::new(2, Overflow::DropOldest);
+        for i in 0..5 {
+            queue.push(i).unwrap();
+        }
+        assert_eq!(queue.dropped(), 3);
+        assert_eq!(queue.pop(), Some((3, 3)));
+        assert_eq!(queue.pop(), Some((4, 4)));
+        queue.close();
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn drop_newest_at_capacity() {
+        let queue = Queue::new(2, Overflow::DropNewest);
+        for i in 0..5 {
+            queue.push(i).unwrap();
+        }
+        assert_eq!(queue.dropped(), 3);
+        assert_eq!(queue.pop(), Some((0, 0)));
+        // The dropped items still use up their numbers
+        queue.push(5).unwrap();
+        assert_eq!(queue.pop(), Some((1, 1)));
+        assert_eq!(queue.pop(), Some((5, 5)));
+    }
+
+    #[test]
+    fn block_at_capacity() {
+        let queue = Arc::new(Queue::new(2, Overflow::Block));
+        queue.push(0).unwrap();
+        queue.push(1).unwrap();
+        let pushed = Arc::new(AtomicBool::new(false));
+        let pusher = {
+            let (queue, pushed) = (queue.clone(), pushed.clone());
+            thread::spawn(move || {
+                queue.push(2).unwrap();
+                pushed.store(true, Ordering::SeqCst);
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!pushed.load(Ordering::SeqCst));
+        assert_eq!(queue.pop(), Some((0, 0)));
+        pusher.join().unwrap();
+        assert!(pushed.load(Ordering::SeqCst));
+        assert_eq!(queue.dropped(), 0);
+        assert_eq!(queue.pop(), Some((1, 1)));
+        assert_eq!(queue.pop(), Some((2, 2)));
+    }
+
+    #[test]
+    fn close_wakes_a_blocked_push() {
+        let queue = Arc::new(Queue::new(1, Overflow::Block));
+        queue.push(0).unwrap();
+        let pusher = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.push(1))
+        };
+        thread::sleep(Duration::from_millis(50));
+        queue.close();
+        match pusher.join().unwrap() {
+            Err(NuiError::Disconnected) => (),
+            other => panic!("expected Disconnected, got {:?}", other),
+        }
+        // What was queued before closing can still be read
+        assert_eq!(queue.pop(), Some((0, 0)));
+        assert_eq!(queue.pop(), None);
+    }
+}
@@ -1,114 +1,694 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::env;
 use std::fs::File;
-use std::sync::mpsc::{channel, Receiver, Sender};
-use data::{SkeletonFeed, color3_vec};
-use nui::tdv::nuitrack::Color3;
-use snap;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use data::{SkeletonDataFeed, DepthFrameFeed, RGBFrameFeed, UserFrameFeed};
+use format::{Header, IndexEntry, Stream};
+use codec::{Codecs, ColorCodec, DepthCodec};
+use errors::NuiError;
+use format;
+use queue::{Overflow, Queue};
 
 const BUFFER_SIZE: usize = 50;
+/// About a second of frames at 30 fps.
+const QUEUE_SIZE: usize = 30;
+const FILE_NAME: &'static str = "recording-{timestamp}.snap";
 
 pub struct Recorder {
-    captures: Vec<Receiver<DataMsg>>,
-    file: File,
+    captures: Vec<Arc<Queue<DataMsg>>>,
+    queue_size: usize,
+    overflow: Overflow,
+    /// `None` until there is a frame for the next file.
+    file: Option<BufWriter<File>>,
+    names: FileNames,
+    rotation: Rotation,
+    /// Every file made so far, the last one is being written.
+    files: Vec<PathBuf>,
+    /// The time stamp of the first frame in the current file.
+    first_time_stamp: u64,
+    /// Start a new file before the next frame.
+    split: bool,
     data: Vec<TimePoint>,
+    buffer_size: usize,
+    /// `None` records whichever streams are captured.
+    streams: Option<Vec<Stream>>,
+    codecs: Codecs,
+    /// When the recorder was created. Shared by all its files.
+    session: u64,
+    created: u64,
+    header_written: bool,
+    /// Where the next frame is written.
+    /// The index is written after this on each flush.
+    data_end: u64,
+    index: Vec<IndexEntry>,
 }
 
 pub struct Capture {
-    tx: Sender<DataMsg>,
+    queue: Arc<Queue<DataMsg>>,
 }
 
-enum DataMsg {
-    Skeleton(Vec<SkeletonFeed>),
-    Depth(Vec<u16>),
-    Color(Vec<Color3>),
-    Size((i32, i32)),
+/// A recorder writing on its own thread. See `Recorder::spawn`.
+pub struct RecorderHandle {
+    captures: Vec<Arc<Queue<DataMsg>>>,
+    thread: Option<JoinHandle<Result<(), NuiError>>>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+/// How the file names of a recording are made.
+struct FileNames {
+    directory: PathBuf,
+    template: String,
+    /// Used instead of the template for the first file.
+    path: Option<PathBuf>,
+}
+
+/// When to start a new file. Whichever limit is hit first wins.
+#[derive(Default)]
+struct Rotation {
+    duration: Option<Duration>,
+    bytes: Option<u64>,
+    frames: Option<usize>,
+}
+
+/// Configures where a `Recorder` writes and what it records.
+///
+/// ```rust,no_run
+/// # use nuitrack_rs::{Recorder, Stream};
+/// let recorder = Recorder::builder()
+///     .directory("/tmp/recordings")
+///     .file_name("session-{timestamp}.snap")
+///     .streams(&[Stream::Skeleton])
+///     .build()
+///     .expect("Failed to create recorder");
+/// ```
+pub struct RecorderBuilder {
+    directory: Option<PathBuf>,
+    path: Option<PathBuf>,
+    file_name: String,
+    buffer_size: usize,
+    queue_size: usize,
+    overflow: Overflow,
+    streams: Option<Vec<Stream>>,
+    codecs: Codecs,
+    rotation: Rotation,
+}
+
+pub(crate) enum DataMsg {
+    Skeleton(SkeletonDataFeed),
+    Depth(DepthFrameFeed),
+    Color(RGBFrameFeed),
+    User(UserFrameFeed),
+}
+
+/// One frame of a recording.
+/// A stream is `None` when it wasn't recorded.
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct TimePoint {
-    pub skeleton: Vec<SkeletonFeed>,
-    pub rows: i32,
-    pub cols: i32,
-    pub depth: Vec<u16>,
-    #[serde(with = "color3_vec")]
-    pub color: Vec<Color3>,
+    pub skeleton: Option<SkeletonDataFeed>,
+    pub depth: Option<DepthFrameFeed>,
+    pub color: Option<RGBFrameFeed>,
+    pub user: Option<UserFrameFeed>,
 }
 
-impl Recorder {
+impl TimePoint {
+    /// The nuitrack time stamp of this frame in microseconds.
+    /// Taken from the first recorded stream of depth, color, user and skeleton.
+    pub fn time_stamp(&self) -> u64 {
+        if let Some(ref d) = self.depth {
+            d.stamp.time_stamp
+        } else if let Some(ref c) = self.color {
+            c.stamp.time_stamp
+        } else if let Some(ref u) = self.user {
+            u.stamp.time_stamp
+        } else if let Some(ref s) = self.skeleton {
+            s.time_stamp
+        } else {
+            0
+        }
+    }
+
+    /// Moves every stream's time stamp by `by` microseconds.
+    pub(crate) fn shift_time_stamps(&mut self, by: i64) {
+        let shift = |t: &mut u64| *t = (*t as i64 + by).max(0) as u64;
+        if let Some(ref mut s) = self.skeleton {
+            shift(&mut s.time_stamp);
+        }
+        if let Some(ref mut d) = self.depth {
+            shift(&mut d.stamp.time_stamp);
+        }
+        if let Some(ref mut c) = self.color {
+            shift(&mut c.stamp.time_stamp);
+        }
+        if let Some(ref mut u) = self.user {
+            shift(&mut u.stamp.time_stamp);
+        }
+    }
+
+    /// The streams stored in this frame.
+    pub fn streams(&self) -> Vec<Stream> {
+        let mut streams = Vec::new();
+        if self.skeleton.is_some() {
+            streams.push(Stream::Skeleton);
+        }
+        if self.depth.is_some() {
+            streams.push(Stream::Depth);
+        }
+        if self.color.is_some() {
+            streams.push(Stream::Color);
+        }
+        if self.user.is_some() {
+            streams.push(Stream::User);
+        }
+        streams
+    }
+
+    /// The resolution of the depth, color or user frame.
+    pub fn size(&self) -> Option<(i32, i32)> {
+        if let Some(ref d) = self.depth {
+            Some((d.rows, d.cols))
+        } else if let Some(ref c) = self.color {
+            Some((c.rows, c.cols))
+        } else if let Some(ref u) = self.user {
+            Some((u.rows, u.cols))
+        } else {
+            None
+        }
+    }
+}
+
+impl RecorderBuilder {
     pub fn new() -> Self {
-        let mut path = env::current_dir().expect("Could find current directory");
-        let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("failed to get time").as_secs();
-        path.push(format!("recording-{}.snap", now));
-        let file = File::create(path).expect("failed to create file");
-        Recorder{ 
-            captures: Vec::new(),
-            file,
-            data: Vec::with_capacity(BUFFER_SIZE),
+        RecorderBuilder {
+            directory: None,
+            path: None,
+            file_name: FILE_NAME.to_string(),
+            buffer_size: BUFFER_SIZE,
+            queue_size: QUEUE_SIZE,
+            overflow: Overflow::Block,
+            streams: None,
+            codecs: Codecs::default(),
+            rotation: Rotation::default(),
         }
     }
 
+    /// The directory recordings are written to.
+    /// Defaults to the current directory.
+    pub fn directory<P: Into<PathBuf>>(mut self, directory: P) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    /// Write to exactly this path.
+    /// Overrides `directory` and `file_name`.
+    pub fn path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// The file name to use inside `directory`.
+    /// `{timestamp}` is replaced with the seconds since the unix epoch
+    /// and `{index}` with the number of the file when rotating.
+    /// Defaults to `recording-{timestamp}.snap`.
+    pub fn file_name<S: Into<String>>(mut self, template: S) -> Self {
+        self.file_name = template.into();
+        self
+    }
+
+    /// How many frames are held in memory before being written.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// How many frames each capture holds while waiting for the recorder.
+    pub fn queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size;
+        self
+    }
+
+    /// What a capture does when its queue is full. Defaults to `Overflow::Block`.
+    ///
+    /// Each capture drops on its own. Every capture is fed once per nuitrack
+    /// update, so the recorder lines the streams up again by update and
+    /// throws away the other streams of an update that lost one.
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// The streams to record.
+    /// Defaults to whichever streams have a capture.
+    ///
+    /// `Recorder::write` fails if one of these streams isn't captured.
+    /// Data captured for any other stream is dropped.
+    pub fn streams(mut self, streams: &[Stream]) -> Self {
+        self.streams = Some(streams.to_vec());
+        self
+    }
+
+    /// How depth frames are compressed. Defaults to `DepthCodec::Rvl`.
+    pub fn depth_codec(mut self, codec: DepthCodec) -> Self {
+        self.codecs.depth = codec;
+        self
+    }
+
+    /// How color frames are compressed. Defaults to `ColorCodec::Bgr`.
+    pub fn color_codec(mut self, codec: ColorCodec) -> Self {
+        self.codecs.color = codec;
+        self
+    }
+
+    /// Start a new file once this much recorded time is in the current one.
+    pub fn rotate_after_duration(mut self, duration: Duration) -> Self {
+        self.rotation.duration = Some(duration);
+        self
+    }
+
+    /// Start a new file once the current one is about this many bytes.
+    pub fn rotate_after_bytes(mut self, bytes: u64) -> Self {
+        self.rotation.bytes = Some(bytes);
+        self
+    }
+
+    /// Start a new file once the current one has this many frames.
+    pub fn rotate_after_frames(mut self, frames: usize) -> Self {
+        self.rotation.frames = Some(frames);
+        self
+    }
+
+    pub fn build(self) -> Result<Recorder, NuiError> {
+        let now = now();
+        let directory = match self.directory {
+            Some(directory) => directory,
+            // Only the exact path is used
+            None if self.path.is_some() => PathBuf::new(),
+            None => env::current_dir()?,
+        };
+        let names = FileNames{ directory, template: self.file_name, path: self.path };
+        Ok(Recorder{ 
+            captures: Vec::new(),
+            queue_size: self.queue_size,
+            overflow: self.overflow,
+            file: None,
+            names,
+            rotation: self.rotation,
+            files: Vec::new(),
+            first_time_stamp: 0,
+            split: false,
+            data: Vec::with_capacity(self.buffer_size),
+            buffer_size: self.buffer_size,
+            streams: self.streams,
+            codecs: self.codecs,
+            session: now,
+            created: now,
+            header_written: false,
+            data_end: 0,
+            index: Vec::new(),
+        })
+    }
+}
+
+impl Recorder {
+    /// Records every captured stream to `recording-{timestamp}.snap`
+    /// in the current directory.
+    pub fn new() -> Result<Self, NuiError> {
+        RecorderBuilder::new().build()
+    }
+
+    pub fn builder() -> RecorderBuilder {
+        RecorderBuilder::new()
+    }
+
+    /// The files written so far. The last one is still being written.
+    /// A file is only made once there is a frame to write to it.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
     pub fn new_capture(&mut self) -> Capture {
-        let (tx, rx) = channel();
-        self.captures.push(rx);
-        Capture{ tx }
+        let queue = Arc::new(Queue::new(self.queue_size, self.overflow));
+        self.captures.push(queue.clone());
+        Capture{ queue }
+    }
+
+    /// Moves the recorder to its own thread which calls `write`
+    /// until `RecorderHandle::stop` is called.
+    /// Create the captures first.
+    pub fn spawn(self) -> RecorderHandle {
+        spawn_writer(self.queues(), self, Recorder::write, Recorder::flush)
+    }
+
+    pub fn write(&mut self) -> Result<(), NuiError> {
+        let frame = self.next_frame()?;
+        self.push_frame(frame)
     }
 
-    pub fn write(&mut self) {
-        if self.data.len() > BUFFER_SIZE {
-            for d in self.data.iter() {
-                let mut snappy = snap::Writer::new(&self.file);
-                serde_json::to_writer(snappy, d).expect("failed to write to file");
+    pub(crate) fn queues(&self) -> Vec<Arc<Queue<DataMsg>>> {
+        self.captures.clone()
+    }
+
+    /// Waits for a frame from every capture.
+    /// Data from an update that some capture dropped is skipped.
+    pub(crate) fn next_frame(&mut self) -> Result<TimePoint, NuiError> {
+        let mut heads = Vec::with_capacity(self.captures.len());
+        for c in self.captures.iter() {
+            heads.push(c.pop().ok_or(NuiError::Disconnected)?);
+        }
+        // Each capture numbers its data by update so catch the others up to the newest
+        loop {
+            let newest = heads.iter().map(|h| h.0).max().unwrap_or(0);
+            if heads.iter().all(|h| h.0 == newest) {
+                break;
+            }
+            for (c, head) in self.captures.iter().zip(heads.iter_mut()) {
+                while head.0 < newest {
+                    c.discard();
+                    *head = c.pop().ok_or(NuiError::Disconnected)?;
+                }
             }
-            self.data.clear();
         }
         let mut sk_data = None;
         let mut d_data = None;
         let mut c_data = None;
-        let mut size = None;
-        for c in self.captures.iter() {
-            let msg = c.recv().expect("failed to recv capture");
+        let mut u_data = None;
+        for (_, msg) in heads {
             match msg {
                 DataMsg::Skeleton(s) => sk_data = Some(s),
                 DataMsg::Depth(d) => d_data = Some(d),
                 DataMsg::Color(c) => c_data = Some(c),
-                DataMsg::Size(s) => size = Some(s),
+                DataMsg::User(u) => u_data = Some(u),
             }
         }
-        let (rows, cols) = size.expect("did not receive size capture");
-        self.data.push(
-            TimePoint {
-                skeleton: sk_data.expect("did not receive skeleton capture"),
-                depth: d_data.expect("did not receive depth capture"),
-                color: c_data.expect("did not receive color capture"),
-                rows,
-                cols,
-            });
+        let frame = TimePoint {
+            skeleton: self.require(Stream::Skeleton, sk_data)?,
+            depth: self.require(Stream::Depth, d_data)?,
+            color: self.require(Stream::Color, c_data)?,
+            user: self.require(Stream::User, u_data)?,
+        };
+        // Images are encoded using their size so it must match the data
+        if let Some(ref d) = frame.depth {
+            check_size(Stream::Depth, d.rows, d.cols, d.data.len())?;
+        }
+        if let Some(ref c) = frame.color {
+            check_size(Stream::Color, c.rows, c.cols, c.data.len())?;
+        }
+        if let Some(ref u) = frame.user {
+            check_size(Stream::User, u.rows, u.cols, u.data.len())?;
+        }
+        Ok(frame)
     }
 
-    pub fn flush(&mut self) {
-        for d in self.data.iter() {
-            let mut snappy = snap::Writer::new(&self.file);
-            serde_json::to_writer(snappy, d).expect("failed to write to file");
+    /// Buffers a frame, writing the buffer out when it is full.
+    pub(crate) fn push_frame(&mut self, frame: TimePoint) -> Result<(), NuiError> {
+        if self.data.len() > self.buffer_size {
+            self.write_data()?;
+        }
+        self.data.push(frame);
+        Ok(())
+    }
+
+    /// Makes the next frame start a new file.
+    pub(crate) fn split(&mut self) {
+        self.split = true;
+    }
+
+    /// Writes any buffered frames followed by the index.
+    /// Later writes overwrite the index and the next flush rewrites it.
+    pub fn flush(&mut self) -> Result<(), NuiError> {
+        self.write_data()?;
+        if let Some(ref mut file) = self.file {
+            if self.header_written {
+                format::write_index(file, &self.index, self.data_end)?;
+            }
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Checks a chosen stream was captured and drops it if it isn't recorded.
+    fn require<T>(&self, stream: Stream, data: Option<T>) -> Result<Option<T>, NuiError> {
+        match self.streams {
+            None => Ok(data),
+            Some(ref streams) if streams.contains(&stream) =>
+                data.map(Some).ok_or(NuiError::MissingStream(stream.name())),
+            Some(_) => Ok(None),
+        }
+    }
+
+    fn write_data(&mut self) -> Result<(), NuiError> {
+        if let (true, Some(file)) = (self.header_written, self.file.as_mut()) {
+            file.seek(SeekFrom::Start(self.data_end))?;
+        }
+        for i in 0..self.data.len() {
+            if let Err(e) = self.write_frame(i) {
+                // Keep the frames that weren't written so they can be retried
+                self.data.drain(..i);
+                return Err(e);
+            }
         }
         self.data.clear();
+        Ok(())
+    }
+
+    fn write_frame(&mut self, i: usize) -> Result<(), NuiError> {
+        if self.header_written && (self.split || self.should_rotate(&self.data[i])) {
+            self.rotate()?;
+        }
+        if self.file.is_none() {
+            self.open_file()?;
+        }
+        let file = self.file.as_mut().expect("The file was just opened");
+        if !self.header_written {
+            // The resolution isn't known until the first frame arrives
+            let frame = &self.data[i];
+            let (rows, cols) = frame.size().unwrap_or((0, 0));
+            let streams = self.streams.clone().unwrap_or_else(|| frame.streams());
+            let part = self.files.len() as u32 - 1;
+            Header::new(streams, rows, cols, self.created, self.codecs, self.session, part)
+                .write(file)?;
+            self.header_written = true;
+            self.data_end = file.seek(SeekFrom::Current(0))?;
+            self.first_time_stamp = frame.time_stamp();
+        }
+        let (len, stats) = format::write_frame(file, &self.data[i], &self.codecs)?;
+        let time_stamp = self.data[i].time_stamp();
+        self.index.push(IndexEntry{ offset: self.data_end, time_stamp, stats });
+        self.data_end += len;
+        Ok(())
+    }
+
+    fn should_rotate(&self, next: &TimePoint) -> bool {
+        let Rotation{ duration, bytes, frames } = self.rotation;
+        let elapsed = next.time_stamp().saturating_sub(self.first_time_stamp);
+        duration.map_or(false, |d| elapsed >= format::micros(d))
+            || bytes.map_or(false, |b| self.data_end + self.index.len() as u64 * format::INDEX_ENTRY_LEN >= b)
+            || frames.map_or(false, |f| self.index.len() >= f)
+    }
+
+    /// Finishes the current file. The next frame starts a new one.
+    fn rotate(&mut self) -> Result<(), NuiError> {
+        if let Some(ref mut file) = self.file {
+            format::write_index(file, &self.index, self.data_end)?;
+            file.flush()?;
+        }
+        self.file = None;
+        self.split = false;
+        self.header_written = false;
+        self.data_end = 0;
+        self.index.clear();
+        Ok(())
+    }
+
+    /// Makes the next file. Nothing is made until there is a frame for it
+    /// so a recorder that never gets one leaves no empty file behind.
+    fn open_file(&mut self) -> Result<(), NuiError> {
+        let now = now();
+        let path = self.names.path(self.files.len(), now);
+        self.file = Some(BufWriter::new(File::create(&path)?));
+        self.files.push(path);
+        self.created = now;
+        Ok(())
+    }
+}
+
+/// Runs `write` on a new thread until the captures are closed, then `flush`.
+pub(crate) fn spawn_writer<T: Send + 'static>(
+    captures: Vec<Arc<Queue<DataMsg>>>,
+    mut writer: T,
+    write: fn(&mut T) -> Result<(), NuiError>,
+    flush: fn(&mut T) -> Result<(), NuiError>) -> RecorderHandle
+{
+    let thread = thread::spawn(move || {
+        loop {
+            match write(&mut writer) {
+                Ok(()) => (),
+                // The captures were closed by stop()
+                Err(NuiError::Disconnected) => break,
+                Err(e) => {
+                    flush(&mut writer).ok();
+                    return Err(e);
+                },
+            }
+        }
+        flush(&mut writer)
+    });
+    RecorderHandle{ captures, thread: Some(thread) }
+}
+
+impl FileNames {
+    /// The path of file number `index`.
+    /// Later files get `-{index}` before the extension if there is no `{index}`.
+    fn path(&self, index: usize, now: u64) -> PathBuf {
+        if let Some(ref path) = self.path {
+            return if index == 0 { path.clone() } else { numbered(path, index) };
+        }
+        let name = self.template
+            .replace("{timestamp}", &now.to_string())
+            .replace("{index}", &index.to_string());
+        let path = self.directory.join(name);
+        if index == 0 || self.template.contains("{index}") {
+            path
+        } else {
+            numbered(&path, index)
+        }
+    }
+}
+
+fn numbered(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+/// Seconds since the unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn check_size(stream: Stream, rows: i32, cols: i32, len: usize) -> Result<(), NuiError> {
+    if rows < 0 || cols < 0 || rows as usize * cols as usize != len {
+        return Err(NuiError::BadFrame(stream.name()));
+    }
+    Ok(())
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Stops captures blocking on a recorder that has gone
+        for c in self.captures.iter() {
+            c.close();
+        }
+    }
+}
+
+impl RecorderHandle {
+    /// The number of frames thrown away because a capture's queue was full,
+    /// or because another capture dropped its part of the same update.
+    /// Counts each stream separately.
+    pub fn dropped(&self) -> u64 {
+        self.captures.iter().map(|c| c.dropped()).sum()
+    }
+
+    /// Writes the frames that are already queued, flushes and joins the thread.
+    /// Returns the first error the thread hit.
+    pub fn stop(mut self) -> Result<(), NuiError> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<(), NuiError> {
+        for c in self.captures.iter() {
+            c.close();
+        }
+        match self.thread.take() {
+            Some(thread) => thread.join()
+                .unwrap_or_else(|_| Err(NuiError::Failed("recorder thread panicked".to_string()))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for RecorderHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.join() {
+            eprintln!("Error stopping recorder: {}", e);
+        }
     }
 }
 
 impl Capture {
-    pub fn capture_skeleton(&self, data: Vec<SkeletonFeed>) {
-        self.tx.send(DataMsg::Skeleton(data)).expect("Failed to send skeleton data");
+    /// Fails if the recorder has been dropped or stopped.
+    /// Blocks or drops a frame if the queue is full, depending on the `Overflow`.
+    pub fn capture_skeleton(&self, data: SkeletonDataFeed) -> Result<(), NuiError> {
+        self.send(DataMsg::Skeleton(data))
     }
     
-    pub fn capture_depth(&self, data: Vec<u16>) {
-        self.tx.send(DataMsg::Depth(data)).expect("Failed to send depth data");
+    pub fn capture_depth(&self, data: DepthFrameFeed) -> Result<(), NuiError> {
+        self.send(DataMsg::Depth(data))
     }
     
-    pub fn capture_color(&self, data: Vec<Color3>) {
-        self.tx.send(DataMsg::Color(data)).expect("Failed to send color data");
+    pub fn capture_color(&self, data: RGBFrameFeed) -> Result<(), NuiError> {
+        self.send(DataMsg::Color(data))
+    }
+
+    pub fn capture_user(&self, data: UserFrameFeed) -> Result<(), NuiError> {
+        self.send(DataMsg::User(data))
+    }
+
+    /// The number of frames this capture threw away because its queue was full,
+    /// or because another capture dropped its part of the same update.
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped()
     }
 
-    pub fn capture_size(&self, data: (i32, i32)) {
-        self.tx.send(DataMsg::Size(data)).expect("Failed to send color data");
+    fn send(&self, msg: DataMsg) -> Result<(), NuiError> {
+        self.queue.push(msg)
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        // Lets the recorder finish the frames already queued then stop
+        self.queue.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skeleton(time_stamp: u64) -> SkeletonDataFeed {
+        SkeletonDataFeed{ time_stamp, skeletons: Vec::new() }
+    }
+
+    #[test]
+    fn realignment_counts_as_dropped() {
+        let mut recorder = Recorder::builder()
+            .path(env::temp_dir().join("nuitrack-rs-recorder-unused.snap"))
+            .queue_size(2)
+            .overflow(Overflow::DropOldest)
+            .build().unwrap();
+        let first = recorder.new_capture();
+        let second = recorder.new_capture();
+        // The first capture loses update 0 to a full queue
+        for i in 0..3 {
+            first.capture_skeleton(skeleton(i)).unwrap();
+        }
+        for i in 0..2 {
+            second.capture_skeleton(skeleton(i)).unwrap();
+        }
+        assert_eq!(first.dropped(), 1);
+        assert_eq!(second.dropped(), 0);
+        // So the second capture throws its part of update 0 away
+        assert_eq!(recorder.next_frame().unwrap().time_stamp(), 1);
+        assert_eq!(first.dropped(), 1);
+        assert_eq!(second.dropped(), 1);
     }
 }
@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use errors::NuiError;
+use format;
+use recorder::{self, Capture, Recorder, RecorderHandle, TimePoint};
+
+/// What starts a recording.
+pub enum Trigger {
+    /// Fires on every frame where `SkeletonData` has at least one skeleton,
+    /// so recording carries on while someone is in view.
+    UserPresent,
+    /// Fires whenever the function returns true for a frame.
+    When(Box<FnMut(&TimePoint) -> bool + Send + 'static>),
+}
+
+/// Fires a `TriggeredRecorder` from another thread.
+#[derive(Clone)]
+pub struct TriggerHandle {
+    fired: Arc<AtomicBool>,
+}
+
+/// Keeps the last few seconds of frames in memory and only writes
+/// to disk when a trigger fires.
+///
+/// Each event is written to its own file with the frames from `pre_roll`
+/// before the trigger up to `post_roll` after the last time it fired.
+/// Use `Recorder::builder()` to choose where the files go.
+///
+/// ```rust,no_run
+/// # use nuitrack_rs::{Recorder, Trigger, TriggeredRecorder};
+/// # use std::time::Duration;
+/// let recorder = Recorder::builder()
+///     .file_name("event-{timestamp}-{index}.snap")
+///     .build()
+///     .expect("Couldn't create recorder");
+/// let mut recorder = TriggeredRecorder::new(recorder)
+///     .pre_roll(Duration::from_secs(3))
+///     .post_roll(Duration::from_secs(2))
+///     .trigger(Trigger::UserPresent);
+/// let capture = recorder.new_capture();
+/// ```
+pub struct TriggeredRecorder {
+    recorder: Recorder,
+    triggers: Vec<Trigger>,
+    fired: Arc<AtomicBool>,
+    pre_roll: Duration,
+    post_roll: Duration,
+    /// The frames from the last `pre_roll`, oldest first.
+    ring: VecDeque<TimePoint>,
+    /// While recording, the time stamp after which the event ends.
+    until: Option<u64>,
+    events: usize,
+}
+
+impl TriggeredRecorder {
+    /// Keeps 5 seconds before and after a trigger.
+    /// Only fires when `trigger_now` or a `TriggerHandle` is called
+    /// until triggers are added.
+    pub fn new(recorder: Recorder) -> Self {
+        TriggeredRecorder {
+            recorder,
+            triggers: Vec::new(),
+            fired: Arc::new(AtomicBool::new(false)),
+            pre_roll: Duration::from_secs(5),
+            post_roll: Duration::from_secs(5),
+            ring: VecDeque::new(),
+            until: None,
+            events: 0,
+        }
+    }
+
+    /// How much to keep from before the trigger.
+    pub fn pre_roll(mut self, pre_roll: Duration) -> Self {
+        self.pre_roll = pre_roll;
+        self
+    }
+
+    /// How long to carry on recording after the trigger last fired.
+    pub fn post_roll(mut self, post_roll: Duration) -> Self {
+        self.post_roll = post_roll;
+        self
+    }
+
+    /// Adds a trigger. Any trigger firing starts or extends a recording.
+    pub fn trigger(mut self, trigger: Trigger) -> Self {
+        self.triggers.push(trigger);
+        self
+    }
+
+    pub fn new_capture(&mut self) -> Capture {
+        self.recorder.new_capture()
+    }
+
+    /// Fires on the next frame.
+    pub fn trigger_now(&self) {
+        self.fired.store(true, Ordering::SeqCst);
+    }
+
+    pub fn trigger_handle(&self) -> TriggerHandle {
+        TriggerHandle{ fired: self.fired.clone() }
+    }
+
+    /// The number of events recorded so far, including one in progress.
+    pub fn events(&self) -> usize {
+        self.events
+    }
+
+    /// The files written so far. The last one may still be being written.
+    /// There are none until a trigger fires.
+    pub fn files(&self) -> &[PathBuf] {
+        self.recorder.files()
+    }
+
+    /// Moves the recorder to its own thread. See `Recorder::spawn`.
+    pub fn spawn(self) -> RecorderHandle {
+        recorder::spawn_writer(self.recorder.queues(), self, TriggeredRecorder::write, TriggeredRecorder::flush)
+    }
+
+    /// Waits for the next frame and either keeps it for the pre-roll or records it.
+    pub fn write(&mut self) -> Result<(), NuiError> {
+        let frame = self.recorder.next_frame()?;
+        let time_stamp = frame.time_stamp();
+        if self.fire(&frame) {
+            if self.until.is_none() {
+                self.events += 1;
+            }
+            self.until = Some(time_stamp + format::micros(self.post_roll));
+        }
+        match self.until {
+            Some(until) if time_stamp <= until => {
+                while let Some(f) = self.ring.pop_front() {
+                    self.recorder.push_frame(f)?;
+                }
+                self.recorder.push_frame(frame)
+            },
+            Some(_) => {
+                // The event is over so finish its file
+                self.until = None;
+                self.recorder.flush()?;
+                self.recorder.split();
+                self.keep(frame);
+                Ok(())
+            },
+            None => {
+                self.keep(frame);
+                Ok(())
+            },
+        }
+    }
+
+    /// Writes the current event, if there is one.
+    /// Frames kept for the pre-roll are not written.
+    pub fn flush(&mut self) -> Result<(), NuiError> {
+        self.recorder.flush()
+    }
+
+    fn fire(&mut self, frame: &TimePoint) -> bool {
+        let mut fired = self.fired.swap(false, Ordering::SeqCst);
+        for trigger in self.triggers.iter_mut() {
+            fired |= match *trigger {
+                Trigger::UserPresent => frame.skeleton
+                    .as_ref()
+                    .map_or(false, |s| !s.skeletons.is_empty()),
+                Trigger::When(ref mut f) => f(frame),
+            };
+        }
+        fired
+    }
+
+    /// Adds a frame to the pre-roll and forgets frames older than it.
+    fn keep(&mut self, frame: TimePoint) {
+        let start = frame.time_stamp().saturating_sub(format::micros(self.pre_roll));
+        self.ring.push_back(frame);
+        while self.ring.front().map_or(false, |f| f.time_stamp() < start) {
+            self.ring.pop_front();
+        }
+    }
+}
+
+impl TriggerHandle {
+    /// Fires on the recorder's next frame.
+    pub fn fire(&self) {
+        self.fired.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{BufReader, Seek, SeekFrom};
+    use std::process;
+    use data::SkeletonDataFeed;
+    use format::{self, Header};
+    use super::*;
+
+    /// Frames are 33ms apart with 100ms of pre-roll and 50ms of post-roll.
+    fn triggered(name: &str) -> (TriggeredRecorder, Capture, PathBuf) {
+        let path = env::temp_dir().join(format!("nuitrack-rs-triggered-{}-{}.snap", name, process::id()));
+        let recorder = Recorder::builder().path(&path).buffer_size(1).build().unwrap();
+        let mut triggered = TriggeredRecorder::new(recorder)
+            .pre_roll(Duration::from_millis(100))
+            .post_roll(Duration::from_millis(50));
+        let capture = triggered.new_capture();
+        (triggered, capture, path)
+    }
+
+    /// Records `count` frames, firing the trigger on the frames in `fire_at`.
+    fn record(triggered: &mut TriggeredRecorder, capture: &Capture, count: u64, fire_at: &[u64]) {
+        for i in 0..count {
+            if fire_at.contains(&i) {
+                triggered.trigger_now();
+            }
+            capture.capture_skeleton(SkeletonDataFeed{ time_stamp: i * 33_000, skeletons: Vec::new() }).unwrap();
+            triggered.write().unwrap();
+        }
+        triggered.flush().unwrap();
+    }
+
+    /// The frame numbers written to each file, which are then removed.
+    fn written(triggered: &TriggeredRecorder) -> Vec<Vec<u64>> {
+        triggered.files().iter().map(|path| {
+            let mut reader = BufReader::new(File::open(path).unwrap());
+            Header::read(&mut reader).unwrap();
+            let data_start = reader.seek(SeekFrom::Current(0)).unwrap();
+            let index = format::read_index(&mut reader, data_start).unwrap();
+            fs::remove_file(path).unwrap();
+            index.iter().map(|e| e.time_stamp / 33_000).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn no_event_leaves_no_file() {
+        let (mut triggered, capture, path) = triggered("none");
+        record(&mut triggered, &capture, 20, &[]);
+        assert_eq!(triggered.events(), 0);
+        assert!(triggered.files().is_empty());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn pre_roll_and_post_roll() {
+        let (mut triggered, capture, _) = triggered("roll");
+        record(&mut triggered, &capture, 20, &[10]);
+        assert_eq!(triggered.events(), 1);
+        // 100ms before frame 10 and 50ms after it
+        assert_eq!(written(&triggered), vec![vec![6, 7, 8, 9, 10, 11]]);
+    }
+
+    #[test]
+    fn trigger_during_post_roll_extends_the_event() {
+        let (mut triggered, capture, _) = triggered("extend");
+        record(&mut triggered, &capture, 20, &[10, 11]);
+        assert_eq!(triggered.events(), 1);
+        assert_eq!(written(&triggered), vec![vec![6, 7, 8, 9, 10, 11, 12]]);
+    }
+
+    #[test]
+    fn each_event_gets_a_file() {
+        let (mut triggered, capture, _) = triggered("two");
+        record(&mut triggered, &capture, 30, &[5, 20]);
+        assert_eq!(triggered.events(), 2);
+        assert_eq!(written(&triggered), vec![vec![1, 2, 3, 4, 5, 6], vec![16, 17, 18, 19, 20, 21]]);
+    }
+}